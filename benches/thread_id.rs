@@ -0,0 +1,54 @@
+//! Compares logging records with and without caching the thread id.
+//!
+//! Run with `cargo bench --bench thread_id`. Each mode runs in a separate
+//! process because the logger is initialized once per process.
+
+use std::{env, process::Command};
+
+/// Number of encoded records per run.
+const ITERATIONS: u32 = 1_000_000;
+
+/// Environment variable selecting the mode of a child process.
+const MODE: &str = "THREAD_ID_BENCH_CACHE";
+
+fn main() {
+    match env::var(MODE) {
+        Ok(cache) => run(cache == "1"),
+        Err(_) => {
+            for cache in ["1", "0"] {
+                let status = Command::new(env::current_exe().expect("failed to get executable"))
+                    .env(MODE, cache)
+                    .status()
+                    .expect("failed to run benchmark");
+                assert!(status.success());
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn run(cache: bool) {
+    use android_logd_logger::{Buffer, Priority};
+    use std::{io, time::Instant};
+
+    // Records are formatted and discarded. Each record queries the thread id.
+    let logger = android_logd_logger::builder()
+        .cache_thread_id(cache)
+        .target_writer(Box::new(io::sink()))
+        .init();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(logger.log_with("tag", Priority::Info, Buffer::Main, "message")).ok();
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "cache_thread_id({}): {:.1} ns per record",
+        cache,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
+
+#[cfg(target_os = "windows")]
+fn run(_: bool) {}
@@ -11,7 +11,7 @@ fn main() -> Result<(), Error> {
 
     write_event_now(1, "test")?;
 
-    let value: Vec<EventValue> = vec![1.into(), "one".into(), 123.3.into()].into();
+    let value: Vec<EventValue> = vec![1.into(), "one".into(), 123.3.into()];
     write_event_now(2, value)?;
 
     write_event_now(3, ())?;
@@ -0,0 +1,102 @@
+use crate::Record;
+use std::fmt::Write as _;
+#[cfg(target_os = "android")]
+use {
+    crate::Error,
+    std::{
+        fs::{File, OpenOptions},
+        io::{self, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            OnceLock,
+        },
+    },
+};
+
+/// Trace marker files of tracefs and debugfs.
+#[cfg(target_os = "android")]
+const TRACE_MARKERS: [&str; 2] = ["/sys/kernel/tracing/trace_marker", "/sys/kernel/debug/tracing/trace_marker"];
+
+/// Maximum length of a marker. Longer messages are truncated.
+const MAX_MARKER_LEN: usize = 1024;
+
+/// Set once writing to the trace marker failed.
+#[cfg(target_os = "android")]
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Open trace marker or the error of opening it.
+#[cfg(target_os = "android")]
+static TRACE_MARKER: OnceLock<io::Result<File>> = OnceLock::new();
+
+/// Write an instant event for `record` to the trace marker.
+///
+/// Returns the error of the first failed write. Writing is disabled
+/// afterwards and further calls return `Ok`.
+#[cfg(target_os = "android")]
+pub(crate) fn log(record: &Record) -> Result<(), Error> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let result = match TRACE_MARKER.get_or_init(open).as_ref() {
+        Ok(mut file) => file.write_all(marker_line(record).as_bytes()),
+        Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+    };
+
+    match result {
+        Err(e) if !DISABLED.swap(true, Ordering::Relaxed) => Err(Error::Io(e)),
+        _ => Ok(()),
+    }
+}
+
+/// Open the first available trace marker.
+#[cfg(target_os = "android")]
+fn open() -> io::Result<File> {
+    let mut error = None;
+    for path in TRACE_MARKERS {
+        match OpenOptions::new().write(true).open(path) {
+            Ok(file) => return Ok(file),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap())
+}
+
+/// Format an atrace instant event with the tag and message of `record`.
+fn marker_line(record: &Record) -> String {
+    let mut line = String::with_capacity(MAX_MARKER_LEN);
+    write!(line, "I|{}|{}: {}", record.pid, record.tag, record.message).ok();
+    if line.len() > MAX_MARKER_LEN {
+        let mut end = MAX_MARKER_LEN;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+    }
+    // Line breaks would end the marker.
+    line.replace('\n', " ")
+}
+
+#[test]
+fn marker() {
+    let record = Record {
+        timestamp: std::time::SystemTime::now(),
+        pid: 42,
+        thread_id: 43,
+        buffer_id: crate::Buffer::Main,
+        tag: "tag",
+        priority: crate::Priority::Info,
+        message: "hello\nworld",
+        uid: None,
+    };
+    assert_eq!(marker_line(&record), "I|42|tag: hello world");
+
+    let message = "ä".repeat(MAX_MARKER_LEN);
+    let record = Record {
+        message: &message,
+        ..record
+    };
+    let line = marker_line(&record);
+    assert!(line.len() <= MAX_MARKER_LEN);
+    assert!(line.ends_with('ä'));
+}
@@ -0,0 +1,83 @@
+use bytes::{Buf, BufMut};
+
+/// Byte order of the multi-byte fields of encoded records and events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Least significant byte first. This is the byte order of logd.
+    #[default]
+    LittleEndian,
+    /// Most significant byte first
+    BigEndian,
+}
+
+impl ByteOrder {
+    /// Write a `u16` in this byte order.
+    pub(crate) fn put_u16<B: BufMut>(self, buffer: &mut B, n: u16) {
+        match self {
+            ByteOrder::LittleEndian => buffer.put_u16_le(n),
+            ByteOrder::BigEndian => buffer.put_u16(n),
+        }
+    }
+
+    /// Write a `u32` in this byte order.
+    pub(crate) fn put_u32<B: BufMut>(self, buffer: &mut B, n: u32) {
+        match self {
+            ByteOrder::LittleEndian => buffer.put_u32_le(n),
+            ByteOrder::BigEndian => buffer.put_u32(n),
+        }
+    }
+
+    /// Write an `i32` in this byte order.
+    pub(crate) fn put_i32<B: BufMut>(self, buffer: &mut B, n: i32) {
+        match self {
+            ByteOrder::LittleEndian => buffer.put_i32_le(n),
+            ByteOrder::BigEndian => buffer.put_i32(n),
+        }
+    }
+
+    /// Write an `i64` in this byte order.
+    pub(crate) fn put_i64<B: BufMut>(self, buffer: &mut B, n: i64) {
+        match self {
+            ByteOrder::LittleEndian => buffer.put_i64_le(n),
+            ByteOrder::BigEndian => buffer.put_i64(n),
+        }
+    }
+
+    /// Write an `f32` in this byte order.
+    pub(crate) fn put_f32<B: BufMut>(self, buffer: &mut B, n: f32) {
+        match self {
+            ByteOrder::LittleEndian => buffer.put_f32_le(n),
+            ByteOrder::BigEndian => buffer.put_f32(n),
+        }
+    }
+
+    /// Read a `u32` in this byte order.
+    pub(crate) fn get_u32<B: Buf>(self, buffer: &mut B) -> u32 {
+        match self {
+            ByteOrder::LittleEndian => buffer.get_u32_le(),
+            ByteOrder::BigEndian => buffer.get_u32(),
+        }
+    }
+
+    /// Read a `u64` in this byte order.
+    pub(crate) fn get_u64<B: Buf>(self, buffer: &mut B) -> u64 {
+        match self {
+            ByteOrder::LittleEndian => buffer.get_u64_le(),
+            ByteOrder::BigEndian => buffer.get_u64(),
+        }
+    }
+}
+
+#[test]
+fn put() {
+    let mut buffer = Vec::new();
+    ByteOrder::LittleEndian.put_u16(&mut buffer, 0x0102);
+    ByteOrder::BigEndian.put_u16(&mut buffer, 0x0102);
+    ByteOrder::LittleEndian.put_u32(&mut buffer, 0x01020304);
+    ByteOrder::BigEndian.put_u32(&mut buffer, 0x01020304);
+    assert_eq!(buffer, [2, 1, 1, 2, 4, 3, 2, 1, 1, 2, 3, 4]);
+
+    let mut buffer = &buffer[4..];
+    assert_eq!(ByteOrder::LittleEndian.get_u32(&mut buffer), 0x01020304);
+    assert_eq!(ByteOrder::BigEndian.get_u32(&mut buffer), 0x01020304);
+}
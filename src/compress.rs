@@ -0,0 +1,64 @@
+use crate::Error;
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+/// Marker byte prepended to compressed pmsg messages.
+///
+/// The marker never occurs in UTF-8 encoded text and allows readers to
+/// distinguish compressed from plain messages. It is followed by the raw
+/// deflate stream of the message chunk.
+pub const COMPRESSED_MARKER: u8 = 0xff;
+
+/// Upper bound for the size of a decompressed message.
+const MAX_DECOMPRESSED_LEN: usize = 64 * 1024;
+
+/// Compress `message`. Returns `None` if the compressed message including
+/// the marker is not smaller than the original.
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+pub(crate) fn compress(message: &[u8]) -> Option<Vec<u8>> {
+    // Favor speed because this runs on the log path.
+    const LEVEL: u8 = 1;
+
+    let compressed = compress_to_vec(message, LEVEL);
+    if compressed.len() + 1 < message.len() {
+        let mut result = Vec::with_capacity(compressed.len() + 1);
+        result.push(COMPRESSED_MARKER);
+        result.extend_from_slice(&compressed);
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Decompress a message read from the pstore.
+///
+/// Messages without the [`COMPRESSED_MARKER`] prefix are returned unchanged.
+///
+/// # Examples
+///
+/// ```
+/// # use android_logd_logger::decompress;
+///
+/// assert_eq!(decompress(b"plain message").unwrap(), b"plain message");
+/// ```
+pub fn decompress(message: &[u8]) -> Result<Vec<u8>, Error> {
+    match message.split_first() {
+        Some((&COMPRESSED_MARKER, compressed)) => decompress_to_vec_with_limit(compressed, MAX_DECOMPRESSED_LEN)
+            .map_err(|e| Error::Decompress(format!("{:?}", e.status))),
+        _ => Ok(message.to_vec()),
+    }
+}
+
+#[test]
+fn round_trip() {
+    let message = "a long and repetitive message ".repeat(100);
+    let compressed = compress(message.as_bytes()).expect("failed to compress");
+    assert_eq!(compressed[0], COMPRESSED_MARKER);
+    assert!(compressed.len() < message.len());
+    assert_eq!(decompress(&compressed).unwrap(), message.as_bytes());
+}
+
+#[test]
+fn incompressible() {
+    assert_eq!(compress(b"short"), None);
+    assert!(decompress(&[COMPRESSED_MARKER, 1, 2, 3]).is_err());
+}
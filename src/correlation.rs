@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+
+thread_local! {
+    /// Correlation id of the current thread.
+    static CORRELATION_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Key of the correlation id in the context of records.
+const CONTEXT_KEY: &str = "correlation_id";
+
+/// Rendering of the context of a record. See [`Builder::context_format`](crate::Builder::context_format).
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ContextFormat {
+    /// `[key=value] message`
+    #[default]
+    Bracketed,
+    /// `key=value message`
+    Prefixed,
+    /// Renders the key, the value and the message
+    Custom(fn(&str, &str, &str) -> String),
+}
+
+impl ContextFormat {
+    /// Renders `message` with the correlation id of the current thread.
+    /// Returns `None` if the thread has no correlation id.
+    pub(crate) fn render(self, message: &str) -> Option<String> {
+        CORRELATION_ID.with(|id| {
+            let id = id.borrow();
+            let id = id.as_deref()?;
+            Some(match self {
+                ContextFormat::Bracketed => format!("[{}={}] {}", CONTEXT_KEY, id, message),
+                ContextFormat::Prefixed => format!("{}={} {}", CONTEXT_KEY, id, message),
+                ContextFormat::Custom(render) => render(CONTEXT_KEY, id, message),
+            })
+        })
+    }
+}
+
+/// Sets the correlation id of the current thread.
+///
+/// If enabled with [`Builder::event_correlation`](crate::Builder::event_correlation)
+/// the id is prepended to the events written on this thread.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::set_correlation_id("request-42");
+/// assert_eq!(android_logd_logger::correlation_id().as_deref(), Some("request-42"));
+/// ```
+pub fn set_correlation_id<S: Into<String>>(id: S) {
+    CORRELATION_ID.with(|current| *current.borrow_mut() = Some(id.into()));
+}
+
+/// Clears the correlation id of the current thread.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::set_correlation_id("request-42");
+/// android_logd_logger::clear_correlation_id();
+/// assert_eq!(android_logd_logger::correlation_id(), None);
+/// ```
+pub fn clear_correlation_id() {
+    CORRELATION_ID.with(|current| current.borrow_mut().take());
+}
+
+/// Returns the correlation id of the current thread.
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.with(|current| current.borrow().clone())
+}
+
+/// Runs `f` with the correlation id `id` and restores the previous id afterwards.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::with_correlation_id("request-42", || {
+///     android_logd_logger::write_event_now(1, "handled").unwrap();
+/// });
+/// assert_eq!(android_logd_logger::correlation_id(), None);
+/// ```
+pub fn with_correlation_id<S: Into<String>, F: FnOnce() -> R, R>(id: S, f: F) -> R {
+    /// Restores the previous id on drop, also if `f` panics.
+    struct Restore(Option<String>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CORRELATION_ID.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CORRELATION_ID.with(|current| current.borrow_mut().replace(id.into()));
+    let _restore = Restore(previous);
+    f()
+}
+
+#[test]
+fn scoped() {
+    set_correlation_id("outer");
+    with_correlation_id("inner", || assert_eq!(correlation_id().as_deref(), Some("inner")));
+    assert_eq!(correlation_id().as_deref(), Some("outer"));
+    clear_correlation_id();
+    assert_eq!(correlation_id(), None);
+}
+
+#[test]
+fn context_rendered() {
+    assert_eq!(ContextFormat::Bracketed.render("message"), None);
+    with_correlation_id("42", || {
+        assert_eq!(
+            ContextFormat::Bracketed.render("message").as_deref(),
+            Some("[correlation_id=42] message")
+        );
+        assert_eq!(
+            ContextFormat::Prefixed.render("message").as_deref(),
+            Some("correlation_id=42 message")
+        );
+        let custom = ContextFormat::Custom(|key, value, message| format!("{} ({}: {})", message, key, value));
+        assert_eq!(custom.render("message").as_deref(), Some("message (correlation_id: 42)"));
+    });
+}
@@ -0,0 +1,58 @@
+use crate::{write_event_buffer_now, Buffer, Error, EventTag};
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::OnceLock};
+
+/// Maximum number of counters accumulated in memory.
+const MAX_COUNTERS: usize = 256;
+
+/// Running totals of the counters written with [`write_counter_event`].
+static TOTALS: OnceLock<Mutex<HashMap<String, i64>>> = OnceLock::new();
+
+/// Write a counter event with the timestamp now to `Buffer::Stats`
+///
+/// The value of the event is a list of `name` and `delta`. The running total
+/// of the counter is accumulated in memory and returned by
+/// [`Logger::counter_value`](crate::Logger::counter_value). At most 256
+/// counters are accumulated, increments of further counters are written
+/// but not accumulated.
+/// ```
+/// use android_logd_logger::write_counter_event;
+/// let logger = android_logd_logger::builder().init();
+///
+/// write_counter_event(1, "requests", 2).unwrap();
+/// write_counter_event(1, "requests", 3).unwrap();
+/// assert_eq!(logger.counter_value("requests"), Some(5));
+/// ```
+pub fn write_counter_event(tag: EventTag, name: &str, delta: i64) -> Result<(), Error> {
+    accumulate(name, delta);
+    write_event_buffer_now(Buffer::Stats, tag, (name, delta))
+}
+
+/// Add `delta` to the total of the counter `name`.
+fn accumulate(name: &str, delta: i64) {
+    let mut totals = TOTALS.get_or_init(Default::default).lock();
+    let full = totals.len() >= MAX_COUNTERS;
+    match totals.get_mut(name) {
+        Some(total) => *total = total.saturating_add(delta),
+        None if !full => {
+            totals.insert(name.to_string(), delta);
+        }
+        None => (),
+    }
+}
+
+/// Returns the running total of the counter `name`.
+pub(crate) fn value(name: &str) -> Option<i64> {
+    TOTALS.get()?.lock().get(name).copied()
+}
+
+#[test]
+fn accumulated() {
+    accumulate("counter_test", 1);
+    accumulate("counter_test", -3);
+    assert_eq!(value("counter_test"), Some(-2));
+    accumulate("counter_test", i64::MAX);
+    accumulate("counter_test", i64::MAX);
+    assert_eq!(value("counter_test"), Some(i64::MAX));
+    assert_eq!(value("counter_unknown"), None);
+}
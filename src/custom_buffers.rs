@@ -0,0 +1,51 @@
+use crate::Buffer;
+use parking_lot::RwLock;
+
+/// Registered names of custom buffers.
+static CUSTOM_BUFFERS: RwLock<Vec<(String, u8)>> = parking_lot::const_rwlock(Vec::new());
+
+/// Registry of named custom buffers.
+///
+/// Custom buffers are registered once by name and resolved with
+/// [`Buffer::custom_named`] instead of repeating the buffer id.
+///
+/// # Examples
+///
+/// ```
+/// # use android_logd_logger::{Buffer, CustomBuffers};
+///
+/// CustomBuffers::register("my_buffer", 8);
+/// assert!(matches!(Buffer::custom_named("my_buffer"), Some(Buffer::Custom(8))));
+/// ```
+pub struct CustomBuffers;
+
+impl CustomBuffers {
+    /// Registers the custom buffer `id` as `name`. An existing registration of
+    /// `name` is replaced.
+    pub fn register(name: &str, id: u8) {
+        let mut buffers = CUSTOM_BUFFERS.write();
+        match buffers.iter_mut().find(|(n, _)| n == name) {
+            Some((_, i)) => *i = id,
+            None => buffers.push((name.to_string(), id)),
+        }
+    }
+
+    /// Returns the custom buffer registered as `name`.
+    pub fn get(name: &str) -> Option<Buffer> {
+        CUSTOM_BUFFERS
+            .read()
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, id)| Buffer::Custom(*id))
+    }
+}
+
+#[test]
+fn register() {
+    CustomBuffers::register("test_a", 10);
+    CustomBuffers::register("test_b", 11);
+    CustomBuffers::register("test_a", 12);
+    assert!(matches!(CustomBuffers::get("test_a"), Some(Buffer::Custom(12))));
+    assert!(matches!(CustomBuffers::get("test_b"), Some(Buffer::Custom(11))));
+    assert!(CustomBuffers::get("test_c").is_none());
+}
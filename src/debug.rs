@@ -0,0 +1,280 @@
+//! Helpers for inspecting encoded log records and events.
+
+use crate::{byte_order, ByteOrder, EventValue};
+use std::{convert::TryInto, fmt::Write};
+
+/// Length of the header common to records and events: buffer id, thread id,
+/// seconds and fraction of the timestamp.
+const HEADER_LEN: usize = 1 + 2 + 4 + 4;
+
+/// Returns a human readable description of a datagram sent to logd.
+///
+/// Datagrams of the binary buffers `events`, `stats` and `security` are
+/// described as events, datagrams of other buffers as log records. Input
+/// that cannot be decoded is described by its length and a hex dump of the
+/// leading bytes. The multi-byte fields are decoded in the configured
+/// [`ByteOrder`].
+///
+/// # Examples
+///
+/// ```
+/// let record = [
+///     0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 4, b't', b'a', b'g', 0, b'h', b'i', 0,
+/// ];
+/// assert_eq!(
+///     android_logd_logger::debug::describe(&record),
+///     "record buffer=main tid=1 time=1.000000000 priority=I tag=\"tag\" message=\"hi\""
+/// );
+///
+/// assert_eq!(android_logd_logger::debug::describe(&[1, 2]), "unknown (2 bytes): 01 02");
+/// ```
+pub fn describe(buffer: &[u8]) -> String {
+    let description = match buffer.first() {
+        Some(2) | Some(5) | Some(6) => describe_event(buffer, byte_order()),
+        Some(_) => describe_record(buffer, byte_order()),
+        None => None,
+    };
+    description.unwrap_or_else(|| describe_unknown(buffer))
+}
+
+/// Detects gaps in a stream of sequence numbers.
+///
+/// Feed the sequence numbers embedded in received records in the order of
+/// reception. A number larger than the successor of the previous one
+/// indicates dropped records. A number that is not larger than the previous
+/// one is considered a restart of the sender and starts a new stream.
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::debug::SequenceTracker;
+///
+/// let mut tracker = SequenceTracker::new();
+/// assert_eq!(tracker.track(1), None);
+/// assert_eq!(tracker.track(2), None);
+/// assert_eq!(tracker.track(5), Some(2));
+/// assert_eq!(tracker.dropped(), 2);
+/// assert_eq!(tracker.gaps(), 1);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct SequenceTracker {
+    last: Option<u64>,
+    dropped: u64,
+    gaps: u64,
+}
+
+impl SequenceTracker {
+    /// Creates a tracker that has not seen a sequence number yet.
+    pub fn new() -> SequenceTracker {
+        SequenceTracker::default()
+    }
+
+    /// Tracks `sequence` and returns the number of records dropped before it.
+    pub fn track(&mut self, sequence: u64) -> Option<u64> {
+        let gap = match self.last {
+            Some(last) if sequence > last && sequence - last > 1 => Some(sequence - last - 1),
+            _ => None,
+        };
+        if let Some(dropped) = gap {
+            self.dropped = self.dropped.saturating_add(dropped);
+            self.gaps += 1;
+        }
+        self.last = Some(sequence);
+        gap
+    }
+
+    /// Total number of dropped records.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of detected gaps.
+    pub fn gaps(&self) -> u64 {
+        self.gaps
+    }
+}
+
+/// Cursor over an encoded datagram.
+struct Reader<'a> {
+    buffer: &'a [u8],
+    order: ByteOrder,
+}
+
+impl<'a> Reader<'a> {
+    /// Take the next `len` bytes.
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buffer.len() < len {
+            return None;
+        }
+        let (head, tail) = self.buffer.split_at(len);
+        self.buffer = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?.try_into().ok()?;
+        Some(match self.order {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.take(4)?.try_into().ok()?;
+        Some(match self.order {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        })
+    }
+
+    /// Take a null terminated string.
+    fn c_str(&mut self) -> Option<&'a str> {
+        let len = self.buffer.iter().position(|&b| b == 0)?;
+        let s = std::str::from_utf8(self.take(len)?).ok()?;
+        self.take(1)?;
+        Some(s)
+    }
+
+    /// Decode the header and describe it.
+    fn header(&mut self) -> Option<String> {
+        let buffer = buffer_name(self.u8()?);
+        let tid = self.u16()?;
+        let secs = self.u32()?;
+        let subsec = self.u32()?;
+        Some(format!("buffer={} tid={} time={}.{:09}", buffer, tid, secs, subsec))
+    }
+}
+
+/// Describe a log record.
+fn describe_record(buffer: &[u8], order: ByteOrder) -> Option<String> {
+    let mut reader = Reader { buffer, order };
+    let header = reader.header()?;
+    let priority = match reader.u8()? {
+        2 => 'V',
+        3 => 'D',
+        4 => 'I',
+        5 => 'W',
+        6 => 'E',
+        7 => 'F',
+        _ => return None,
+    };
+    let tag = reader.c_str()?;
+    let message = reader.c_str()?;
+    if !reader.buffer.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "record {} priority={} tag={:?} message={:?}",
+        header, priority, tag, message
+    ))
+}
+
+/// Describe an event.
+fn describe_event(buffer: &[u8], order: ByteOrder) -> Option<String> {
+    let mut reader = Reader { buffer, order };
+    let header = reader.header()?;
+    let tag = reader.u32()?;
+    let value = EventValue::decode(&mut reader.buffer, order).ok()?;
+    if !reader.buffer.is_empty() {
+        return None;
+    }
+    let mut description = format!("event {} tag={} value=", header, tag);
+    push_value(&mut description, &value);
+    Some(description)
+}
+
+/// Describe input that is neither a record nor an event.
+fn describe_unknown(buffer: &[u8]) -> String {
+    const MAX_BYTES: usize = HEADER_LEN + 21;
+
+    let mut description = format!("unknown ({} bytes):", buffer.len());
+    for byte in buffer.iter().take(MAX_BYTES) {
+        write!(description, " {:02x}", byte).ok();
+    }
+    if buffer.len() > MAX_BYTES {
+        description.push_str(" ...");
+    }
+    description
+}
+
+/// Append a readable representation of `value`.
+fn push_value(description: &mut String, value: &EventValue) {
+    match value {
+        EventValue::Void => description.push_str("()"),
+        EventValue::Int(n) => write!(description, "{}", n).unwrap_or_default(),
+        EventValue::Long(n) => write!(description, "{}L", n).unwrap_or_default(),
+        EventValue::Float(n) => write!(description, "{:?}f", n).unwrap_or_default(),
+        EventValue::String(s) => write!(description, "{:?}", s).unwrap_or_default(),
+        EventValue::List(values) => {
+            description.push('[');
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    description.push_str(", ");
+                }
+                push_value(description, value);
+            }
+            description.push(']');
+        }
+    }
+}
+
+/// Name of the buffer with the id `id` as used by `logcat`.
+fn buffer_name(id: u8) -> String {
+    match id {
+        0 => "main".into(),
+        1 => "radio".into(),
+        2 => "events".into(),
+        3 => "system".into(),
+        4 => "crash".into(),
+        5 => "stats".into(),
+        6 => "security".into(),
+        id => format!("custom({})", id),
+    }
+}
+
+#[test]
+fn event() {
+    use bytes::BufMut;
+
+    let mut buffer = vec![2, 7, 0, 1, 0, 0, 0, 5, 0, 0, 0];
+    buffer.put_u32_le(42);
+    buffer.extend_from_slice(&EventValue::from((1, "a", 2.5f32)).encode(ByteOrder::LittleEndian));
+    assert_eq!(
+        describe_event(&buffer, ByteOrder::LittleEndian).unwrap(),
+        "event buffer=events tid=7 time=1.000000005 tag=42 value=[1, \"a\", 2.5f]"
+    );
+}
+
+#[test]
+fn garbage() {
+    assert_eq!(describe(&[]), "unknown (0 bytes):");
+    assert_eq!(describe(&[2, 0, 0, 0xff]), "unknown (4 bytes): 02 00 00 ff");
+    // Unknown event type
+    assert_eq!(
+        describe_event(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 9], ByteOrder::LittleEndian),
+        None
+    );
+    // Missing message terminator
+    assert_eq!(
+        describe_record(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, b't', 0, b'm'], ByteOrder::LittleEndian),
+        None
+    );
+}
+
+#[test]
+fn sequence_gaps() {
+    let mut tracker = SequenceTracker::new();
+    assert_eq!(tracker.track(10), None);
+    assert_eq!(tracker.track(13), Some(2));
+    assert_eq!(tracker.track(14), None);
+    // Restart of the sender
+    assert_eq!(tracker.track(0), None);
+    assert_eq!(tracker.track(0), None);
+    assert_eq!(tracker.track(2), Some(1));
+    assert_eq!(tracker.track(u64::MAX), Some(u64::MAX - 3));
+    assert_eq!(tracker.gaps(), 3);
+}
@@ -0,0 +1,115 @@
+use crate::{write_event_buffer, Buffer, Event, EventTag, EventValue};
+use parking_lot::RwLock;
+use std::{
+    cell::Cell,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Instant, SystemTime},
+};
+
+/// Tag of the events written by [`Builder::self_diagnostics_buffer`](crate::Builder::self_diagnostics_buffer).
+///
+/// The value of the events is a list of the kind of the failure, a
+/// description and the number of failures suppressed since the last event.
+pub const SELF_DIAGNOSTICS_EVENT_TAG: EventTag = 0x4c44_4c00;
+
+/// Minimum interval between two diagnostic events in nanoseconds.
+const INTERVAL: u64 = 1_000_000_000;
+
+/// Buffer diagnostic events are written to. `None` disables diagnostic events.
+pub(crate) static BUFFER: RwLock<Option<Buffer>> = parking_lot::const_rwlock(None);
+
+/// Reference for the timestamps of diagnostic events.
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Limiter of the diagnostic events.
+static LIMITER: Limiter = Limiter::new();
+
+/// Limits diagnostic events to one per [`INTERVAL`] and counts the
+/// suppressed failures.
+struct Limiter {
+    /// Time of the last event in nanoseconds since `START` plus one.
+    last: AtomicU64,
+    /// Number of failures not reported since the last event.
+    suppressed: AtomicU64,
+}
+
+impl Limiter {
+    const fn new() -> Limiter {
+        Limiter {
+            last: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    /// Admit an event at `now` in nanoseconds since `START` plus one. Returns
+    /// the number of failures suppressed since the last event or `None` if
+    /// the failure is suppressed.
+    fn admit(&self, now: u64) -> Option<u64> {
+        let last = self.last.load(Ordering::Relaxed);
+        if (last != 0 && now.saturating_sub(last) < INTERVAL)
+            || self
+                .last
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(self.suppressed.swap(0, Ordering::Relaxed))
+    }
+}
+
+thread_local! {
+    /// Set while a diagnostic event is written on this thread.
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Report a logger internal failure of `kind`.
+///
+/// The failure is written as event to the configured buffer. At most one event
+/// is written per second, other failures are counted. Failures caused by
+/// writing a diagnostic event are ignored.
+pub(crate) fn report(kind: &str, description: fmt::Arguments) {
+    let buffer = match *BUFFER.read() {
+        Some(buffer) => buffer,
+        None => return,
+    };
+
+    if REPORTING.with(Cell::get) {
+        return;
+    }
+
+    let now = START.get_or_init(Instant::now).elapsed().as_nanos() as u64 + 1;
+    let suppressed = match LIMITER.admit(now) {
+        Some(suppressed) => suppressed,
+        None => return,
+    };
+
+    let event = Event {
+        timestamp: SystemTime::now(),
+        tag: SELF_DIAGNOSTICS_EVENT_TAG,
+        value: EventValue::List(vec![
+            kind.into(),
+            description.to_string().as_str().into(),
+            (suppressed.min(i64::MAX as u64) as i64).into(),
+        ]),
+    };
+
+    REPORTING.with(|reporting| reporting.set(true));
+    write_event_buffer(buffer, &event).ok();
+    REPORTING.with(|reporting| reporting.set(false));
+}
+
+#[test]
+fn rate_limited() {
+    let limiter = Limiter::new();
+    assert_eq!(limiter.admit(1), Some(0));
+    assert_eq!(limiter.admit(2), None);
+    assert_eq!(limiter.admit(INTERVAL), None);
+    assert_eq!(limiter.admit(INTERVAL + 1), Some(2));
+    assert_eq!(limiter.admit(2 * INTERVAL + 1), Some(0));
+}
@@ -0,0 +1,187 @@
+use crate::{write_event_now, Error, EventTag, EventValue};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Path of the event tag definitions of the system.
+const SYSTEM_EVENT_LOG_TAGS: &str = "/system/etc/event-log-tags";
+
+/// Event tag definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventTagDefinition {
+    /// Numeric tag
+    pub tag: EventTag,
+    /// Optional format descriptor, e.g. `(pid|1|5),(name|3)`
+    pub format: Option<String>,
+}
+
+/// Registry of named event tags.
+///
+/// The registry is built from files in the `event-log-tags` format. Each line
+/// contains the numeric tag, the tag name and an optional format descriptor:
+///
+/// ```text
+/// # comment
+/// 42 answer (to life the universe etc|3)
+/// 314 pi
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct EventTagRegistry {
+    tags: HashMap<String, EventTagDefinition>,
+}
+
+impl EventTagRegistry {
+    /// Reads the event tag definitions of the system from `/system/etc/event-log-tags`.
+    ///
+    /// Comment lines and malformed entries are skipped.
+    pub fn from_system() -> Result<EventTagRegistry, Error> {
+        EventTagRegistry::from_file(SYSTEM_EVENT_LOG_TAGS)
+    }
+
+    /// Reads the event tag definitions from a file in the `event-log-tags` format.
+    ///
+    /// Comment lines and malformed entries are skipped.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<EventTagRegistry, Error> {
+        Ok(EventTagRegistry::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Reads the event tag definitions from a file in the `event-log-tags` format
+    /// and fails on malformed entries. See [`EventTagRegistry::try_parse`].
+    pub fn try_from_file<P: AsRef<Path>>(path: P) -> Result<EventTagRegistry, Error> {
+        EventTagRegistry::try_parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses event tag definitions in the `event-log-tags` format.
+    ///
+    /// Comment lines and malformed entries are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::EventTagRegistry;
+    ///
+    /// let registry = EventTagRegistry::parse("# comment\n42 answer (to life the universe etc|3)\n");
+    /// assert_eq!(registry.tag("answer"), Some(42));
+    /// ```
+    pub fn parse(definitions: &str) -> EventTagRegistry {
+        let tags = definitions
+            .lines()
+            .filter_map(parse_line)
+            .map(|(name, definition)| (name.to_string(), definition))
+            .collect();
+        EventTagRegistry { tags }
+    }
+
+    /// Parses event tag definitions in the `event-log-tags` format and fails on malformed entries.
+    ///
+    /// Empty and comment lines are skipped. Returns [`Error::EventTagDefinition`]
+    /// with the line number of the first malformed entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Error, EventTagRegistry};
+    ///
+    /// let registry = EventTagRegistry::try_parse("# comment\n42 answer (to life the universe etc|3)\n").unwrap();
+    /// assert_eq!(registry.tag("answer"), Some(42));
+    ///
+    /// let error = EventTagRegistry::try_parse("42 answer\nanswer 42\n").unwrap_err();
+    /// assert!(matches!(error, Error::EventTagDefinition(2, _)));
+    /// ```
+    pub fn try_parse(definitions: &str) -> Result<EventTagRegistry, Error> {
+        let mut tags = HashMap::new();
+        for (idx, line) in definitions.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let (name, definition) = parse_line(line).ok_or_else(|| Error::EventTagDefinition(idx + 1, trimmed.to_string()))?;
+            tags.insert(name.to_string(), definition);
+        }
+        Ok(EventTagRegistry { tags })
+    }
+
+    /// Returns the numeric tag of `name`.
+    pub fn tag(&self, name: &str) -> Option<EventTag> {
+        self.tags.get(name).map(|definition| definition.tag)
+    }
+
+    /// Returns the definition of `name`.
+    pub fn get(&self, name: &str) -> Option<&EventTagDefinition> {
+        self.tags.get(name)
+    }
+
+    /// Number of registered tags.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Returns true if no tags are registered.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+}
+
+/// Parses a single line. Returns `None` for empty, comment and malformed lines.
+fn parse_line(line: &str) -> Option<(&str, EventTagDefinition)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (tag, rest) = line.split_once(char::is_whitespace)?;
+    let tag = tag.parse().ok()?;
+    let (name, format) = match rest.trim_start().split_once(char::is_whitespace) {
+        Some((name, format)) => (name, Some(format.trim().to_string())),
+        None => (rest.trim_start(), None),
+    };
+    Some((name, EventTagDefinition { tag, format }))
+}
+
+/// Write an event with the timestamp now to `Buffer::Events` referencing the tag by name
+///
+/// ```
+/// use android_logd_logger::{write_named_event, EventTagRegistry};
+/// android_logd_logger::builder().init();
+///
+/// let registry = EventTagRegistry::parse("42 answer (to life the universe etc|3)");
+/// write_named_event(&registry, "answer", "everything").unwrap();
+/// ```
+pub fn write_named_event<T: Into<EventValue>>(registry: &EventTagRegistry, name: &str, value: T) -> Result<(), Error> {
+    let tag = registry.tag(name).ok_or_else(|| Error::UnknownEventTag(name.to_string()))?;
+    write_event_now(tag, value)
+}
+
+#[test]
+fn parse() {
+    let registry = EventTagRegistry::parse(
+        "# comment\n\
+         \n\
+         42 answer (to life the universe etc|3)\n\
+         314   pi\n\
+         invalid line\n\
+         2718\n",
+    );
+    assert_eq!(registry.len(), 2);
+    assert_eq!(
+        registry.get("answer"),
+        Some(&EventTagDefinition {
+            tag: 42,
+            format: Some("(to life the universe etc|3)".into())
+        })
+    );
+    assert_eq!(registry.tag("pi"), Some(314));
+    assert_eq!(registry.get("pi").unwrap().format, None);
+}
+
+#[test]
+fn try_parse() {
+    let registry = EventTagRegistry::try_parse("# comment\n\n  42 answer (to life the universe etc|3)\n314\tpi\n").unwrap();
+    assert_eq!(registry.tag("answer"), Some(42));
+    assert_eq!(registry.tag("pi"), Some(314));
+
+    for (definitions, line) in [("invalid line", 1), ("1 a\n2718\n", 2), ("1 a\n\n-1 b", 3)] {
+        match EventTagRegistry::try_parse(definitions) {
+            Err(Error::EventTagDefinition(n, _)) => assert_eq!(n, line),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
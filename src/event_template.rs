@@ -0,0 +1,161 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::time::SystemTime;
+
+use crate::{byte_order, max_entry_len, Buffer, ByteOrder, Error, EventTag, EventValue};
+
+/// Type of a list in the serialized event format.
+const EVENT_TYPE_LIST: u8 = 3;
+
+/// Event with a fixed tag and leading values
+///
+/// The tag and the serialized leading values are cached in the template.
+/// [`emit`](EventTemplate::emit) only serializes the varying values and
+/// writes an event with the list of the prefix followed by the values.
+/// The event options configured in the builder, e.g. the process name, are
+/// not applied.
+/// ```
+/// use android_logd_logger::{EventTemplate, EventValue};
+/// android_logd_logger::builder().init();
+///
+/// let template = EventTemplate::new(1, vec!["net".into(), "rx".into()]);
+/// template.emit(1024).unwrap();
+/// template.emit(vec![EventValue::from(1024), 12.into()]).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventTemplate {
+    buffer: Buffer,
+    tag: EventTag,
+    prefix: Vec<EventValue>,
+    encoded: Bytes,
+    order: ByteOrder,
+}
+
+impl EventTemplate {
+    /// Create a template for `Buffer::Events`
+    pub fn new(tag: EventTag, prefix: Vec<EventValue>) -> EventTemplate {
+        EventTemplate::with_buffer(Buffer::Events, tag, prefix)
+    }
+
+    /// Create a template for an explicit buffer
+    /// ```
+    /// use android_logd_logger::{Buffer, EventTemplate};
+    ///
+    /// let template = EventTemplate::with_buffer(Buffer::Stats, 1, vec!["cache".into()]);
+    /// assert_eq!(template.tag(), 1);
+    /// ```
+    pub fn with_buffer(buffer: Buffer, tag: EventTag, prefix: Vec<EventValue>) -> EventTemplate {
+        let order = byte_order();
+        EventTemplate {
+            buffer,
+            tag,
+            encoded: encode_values(&prefix, order),
+            prefix,
+            order,
+        }
+    }
+
+    /// Tag of the events
+    pub fn tag(&self) -> EventTag {
+        self.tag
+    }
+
+    /// Fixed leading values of the events
+    pub fn prefix(&self) -> &[EventValue] {
+        &self.prefix
+    }
+
+    /// Write an event with the timestamp now
+    ///
+    /// The elements of a list `value` are appended to the prefix one by one,
+    /// `EventValue::Void` appends nothing. Returns [`Error::EventSize`] if the
+    /// event exceeds the maximum entry length or the list the maximum number
+    /// of 255 elements.
+    pub fn emit<T: Into<EventValue>>(&self, value: T) -> Result<(), Error> {
+        if !crate::writable() {
+            return Ok(());
+        }
+
+        let values = match value.into() {
+            EventValue::Void => Vec::new(),
+            EventValue::List(values) => values,
+            value => vec![value],
+        };
+
+        #[cfg(target_os = "android")]
+        {
+            let value = self.encode(&values)?;
+            let mut buffer = crate::logd::encode_event_header(
+                self.buffer,
+                crate::thread::id() as u16,
+                SystemTime::now(),
+                self.tag,
+                crate::event_timestamp_unit(),
+            );
+            buffer.put(value);
+            crate::logd::send_event_datagram(self.buffer, &buffer)?;
+        }
+
+        #[cfg(not(target_os = "android"))]
+        {
+            self.encode(&values)?;
+            let values = self.prefix.iter().cloned().chain(values).collect::<Vec<_>>();
+            let event = crate::Event {
+                timestamp: SystemTime::now(),
+                tag: self.tag,
+                value: EventValue::List(values),
+            };
+            crate::events::print_event(self.buffer, &event);
+        }
+
+        Ok(())
+    }
+
+    /// Serialize the list of the prefix and `values`.
+    fn encode(&self, values: &[EventValue]) -> Result<Bytes, Error> {
+        let count = self.prefix.len() + values.len();
+        if count > u8::MAX as usize {
+            return Err(Error::EventSize);
+        }
+        values.iter().try_for_each(EventValue::validate_list_len)?;
+
+        let order = byte_order();
+        let prefix = if order == self.order {
+            self.encoded.clone()
+        } else {
+            encode_values(&self.prefix, order)
+        };
+
+        let size = 1 + 1 + prefix.len() + values.iter().map(EventValue::serialized_size).sum::<usize>();
+        if size > (max_entry_len() - 1 - 2 - 4 - 4 - 4) {
+            return Err(Error::EventSize);
+        }
+
+        let mut buffer = BytesMut::with_capacity(size);
+        buffer.put_u8(EVENT_TYPE_LIST);
+        buffer.put_u8(count as u8);
+        buffer.put(prefix);
+        values.iter().for_each(|value| buffer.put(value.encode(order)));
+        Ok(buffer.freeze())
+    }
+}
+
+/// Serialize `values` back to back.
+fn encode_values(values: &[EventValue], order: ByteOrder) -> Bytes {
+    let mut buffer = BytesMut::new();
+    values.iter().for_each(|value| buffer.put(value.encode(order)));
+    buffer.freeze()
+}
+
+#[test]
+fn encode() {
+    let template = EventTemplate::new(1, vec!["net".into(), 1.into()]);
+    let values = vec![EventValue::from(2i64), "rx".into()];
+    let expected = EventValue::List(vec!["net".into(), 1.into(), 2i64.into(), "rx".into()]).encode(byte_order());
+    assert_eq!(template.encode(&values).unwrap(), expected);
+
+    let values = vec![EventValue::Int(0); 254];
+    assert!(matches!(template.encode(&values), Err(Error::EventSize)));
+
+    let values = vec![EventValue::String("x".repeat(10_000))];
+    assert!(matches!(template.encode(&values), Err(Error::EventSize)));
+}
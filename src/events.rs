@@ -1,11 +1,26 @@
-use bytes::{BufMut, Bytes, BytesMut};
-use std::{iter::FromIterator, time::SystemTime};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{
+    borrow::Cow,
+    convert::TryFrom,
+    iter::{self, FromIterator},
+    sync::{atomic::Ordering, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
 
-use crate::{Buffer, Error, LOGGER_ENTRY_MAX_LEN};
+use crate::{byte_order, max_entry_len, Buffer, ByteOrder, Error, TimestampUnit};
 
 /// Event tag
 pub type EventTag = u32;
 
+/// Maximum nesting of event lists. This is the limit of liblog.
+const MAX_LIST_DEPTH: usize = 8;
+
+const EVENT_TYPE_INT: u8 = 0;
+const EVENT_TYPE_LONG: u8 = 1;
+const EVENT_TYPE_STRING: u8 = 2;
+const EVENT_TYPE_LIST: u8 = 3;
+const EVENT_TYPE_FLOAT: u8 = 4;
+
 /// Event data
 #[derive(Debug, Clone, PartialEq)]
 pub struct Event {
@@ -41,47 +56,196 @@ impl EventValue {
             &EventValue::Void => 0,
             EventValue::Int(_) | EventValue::Float(_) => 1 + 4,
             EventValue::Long(_) => 1 + 8,
-            EventValue::String(s) => 1 + 4 + s.as_bytes().len(),
+            EventValue::String(s) => 1 + 4 + s.len(),
             EventValue::List(l) => 1 + 1 + l.iter().map(EventValue::serialized_size).sum::<usize>(),
         }
     }
 
     /// Serialize the event value into bytes
+    ///
+    /// The number of elements of a list is encoded in a single byte. Lists
+    /// with more than 255 elements produce a malformed event. Use
+    /// [`try_as_bytes`](EventValue::try_as_bytes) to detect them.
     pub fn as_bytes(&self) -> Bytes {
-        const EVENT_TYPE_INT: u8 = 0;
-        const EVENT_TYPE_LONG: u8 = 1;
-        const EVENT_TYPE_STRING: u8 = 2;
-        const EVENT_TYPE_LIST: u8 = 3;
-        const EVENT_TYPE_FLOAT: u8 = 4;
+        self.encode(byte_order())
+    }
+
+    /// Serialize the event value into bytes or fail on lists with more than 255 elements
+    /// ```
+    /// use android_logd_logger::{Error, EventValue};
+    ///
+    /// let value = EventValue::List(vec![EventValue::Int(0); 300]);
+    /// assert!(matches!(value.try_as_bytes(), Err(Error::EventSize)));
+    /// ```
+    pub fn try_as_bytes(&self) -> Result<Bytes, Error> {
+        self.validate_list_len()?;
+        Ok(self.as_bytes())
+    }
+
+    /// Fails if the value contains a list with more than 255 elements.
+    pub(crate) fn validate_list_len(&self) -> Result<(), Error> {
+        match self {
+            EventValue::List(values) if values.len() > u8::MAX as usize => Err(Error::EventSize),
+            EventValue::List(values) => values.iter().try_for_each(EventValue::validate_list_len),
+            _ => Ok(()),
+        }
+    }
 
+    /// Serialize the event value into bytes with the multi-byte fields in `order`.
+    pub(crate) fn encode(&self, order: ByteOrder) -> Bytes {
         let mut buffer = BytesMut::with_capacity(self.serialized_size());
         match self {
             EventValue::Void => (),
             EventValue::Int(num) => {
                 buffer.put_u8(EVENT_TYPE_INT);
-                buffer.put_i32_le(*num);
+                order.put_i32(&mut buffer, *num);
             }
             EventValue::Long(num) => {
                 buffer.put_u8(EVENT_TYPE_LONG);
-                buffer.put_i64_le(*num);
+                order.put_i64(&mut buffer, *num);
             }
             EventValue::Float(num) => {
                 buffer.put_u8(EVENT_TYPE_FLOAT);
-                buffer.put_f32_le(*num);
+                order.put_f32(&mut buffer, *num);
             }
             EventValue::String(string) => {
                 buffer.put_u8(EVENT_TYPE_STRING);
-                buffer.put_u32_le(string.len() as u32);
+                order.put_u32(&mut buffer, string.len() as u32);
                 buffer.put(string.as_bytes());
             }
             EventValue::List(values) => {
                 buffer.put_u8(EVENT_TYPE_LIST);
                 buffer.put_u8(values.len() as u8);
-                values.iter().for_each(|value| buffer.put(value.as_bytes()));
+                values.iter().for_each(|value| buffer.put(value.encode(order)));
             }
         };
         buffer.freeze()
     }
+
+    /// Deserialize an event value serialized with [`as_bytes`](EventValue::as_bytes)
+    ///
+    /// An empty buffer is `EventValue::Void`. Trailing bytes are not consumed.
+    /// ```
+    /// use android_logd_logger::EventValue;
+    ///
+    /// let value = EventValue::from((1, "one", vec![EventValue::from(2.5f32)]));
+    /// assert_eq!(EventValue::from_bytes(&mut value.as_bytes()).unwrap(), value);
+    /// ```
+    pub fn from_bytes<B: Buf>(buffer: &mut B) -> Result<EventValue, Error> {
+        EventValue::decode(buffer, byte_order())
+    }
+
+    /// Deserialize an event value with the multi-byte fields in `order`.
+    pub(crate) fn decode<B: Buf>(buffer: &mut B, order: ByteOrder) -> Result<EventValue, Error> {
+        if buffer.has_remaining() {
+            decode_value(buffer, order, 0)
+        } else {
+            Ok(EventValue::Void)
+        }
+    }
+}
+
+/// Deserialize a value starting with the type tag.
+fn decode_value<B: Buf>(buffer: &mut B, order: ByteOrder, depth: usize) -> Result<EventValue, Error> {
+    fn ensure<B: Buf>(buffer: &B, len: usize) -> Result<(), Error> {
+        if buffer.remaining() < len {
+            Err(Error::Decode("truncated event value".into()))
+        } else {
+            Ok(())
+        }
+    }
+
+    ensure(buffer, 1)?;
+    let value = match buffer.get_u8() {
+        EVENT_TYPE_INT => {
+            ensure(buffer, 4)?;
+            EventValue::Int(order.get_u32(buffer) as i32)
+        }
+        EVENT_TYPE_LONG => {
+            ensure(buffer, 8)?;
+            EventValue::Long(order.get_u64(buffer) as i64)
+        }
+        EVENT_TYPE_FLOAT => {
+            ensure(buffer, 4)?;
+            EventValue::Float(f32::from_bits(order.get_u32(buffer)))
+        }
+        EVENT_TYPE_STRING => {
+            ensure(buffer, 4)?;
+            let len = order.get_u32(buffer) as usize;
+            ensure(buffer, len)?;
+            let bytes = buffer.copy_to_bytes(len);
+            let string = String::from_utf8(bytes.to_vec()).map_err(|e| Error::Decode(e.to_string()))?;
+            EventValue::String(string)
+        }
+        EVENT_TYPE_LIST if depth < MAX_LIST_DEPTH => {
+            ensure(buffer, 1)?;
+            let len = buffer.get_u8();
+            let values = (0..len)
+                .map(|_| decode_value(buffer, order, depth + 1))
+                .collect::<Result<Vec<_>, _>>()?;
+            EventValue::List(values)
+        }
+        EVENT_TYPE_LIST => return Err(Error::Decode("event lists nested too deep".into())),
+        tag => return Err(Error::Decode(format!("unknown event type {}", tag))),
+    };
+    Ok(value)
+}
+
+impl Event {
+    /// Deserialize an event from a datagram sent to logd
+    ///
+    /// The datagram starts with the buffer id, thread id, timestamp and tag
+    /// followed by the value. The buffer and thread id are skipped. The
+    /// fractional part of the timestamp is read in the configured
+    /// [`TimestampUnit`].
+    /// ```
+    /// use android_logd_logger::Event;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let datagram = [2, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 7, 0, 0, 0];
+    /// let event = Event::from_bytes(&mut &datagram[..]).unwrap();
+    /// assert_eq!(event.timestamp, UNIX_EPOCH + Duration::from_secs(1));
+    /// assert_eq!(event.tag, 42);
+    /// assert_eq!(event.value, 7.into());
+    /// ```
+    pub fn from_bytes<B: Buf>(buffer: &mut B) -> Result<Event, Error> {
+        let order = byte_order();
+        if buffer.remaining() < 1 + 2 + 4 + 4 + 4 {
+            return Err(Error::Decode("truncated event header".into()));
+        }
+        buffer.advance(1 + 2);
+        let secs = order.get_u32(buffer) as u64;
+        let subsec = order.get_u32(buffer);
+        let subsec = match crate::event_timestamp_unit() {
+            TimestampUnit::Nanos => Duration::from_nanos(subsec as u64),
+            TimestampUnit::Micros => Duration::from_micros(subsec as u64),
+        };
+        let tag = order.get_u32(buffer);
+        let value = EventValue::decode(buffer, order)?;
+        Ok(Event {
+            timestamp: std::time::UNIX_EPOCH + Duration::from_secs(secs) + subsec,
+            tag,
+            value,
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&EventValue> for serde_json::Value {
+    /// Maps the value to the natural JSON type. Void and floats that are not
+    /// finite map to `null`.
+    fn from(value: &EventValue) -> serde_json::Value {
+        match value {
+            EventValue::Void => serde_json::Value::Null,
+            EventValue::Int(n) => (*n).into(),
+            EventValue::Long(n) => (*n).into(),
+            EventValue::Float(n) => serde_json::Number::from_f64(*n as f64)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            EventValue::String(s) => s.as_str().into(),
+            EventValue::List(values) => values.iter().map(serde_json::Value::from).collect(),
+        }
+    }
 }
 
 impl From<()> for EventValue {
@@ -114,6 +278,64 @@ impl From<&str> for EventValue {
     }
 }
 
+/// ```
+/// use android_logd_logger::EventValue;
+///
+/// assert_eq!(EventValue::from(String::from("text")), EventValue::String("text".into()));
+/// ```
+impl From<String> for EventValue {
+    fn from(v: String) -> Self {
+        EventValue::String(v)
+    }
+}
+
+/// Unsigned 32 bit integers are converted to a `Long` without loss.
+/// ```
+/// use android_logd_logger::EventValue;
+///
+/// assert_eq!(EventValue::from(u32::MAX), EventValue::Long(4_294_967_295));
+/// ```
+impl From<u32> for EventValue {
+    fn from(v: u32) -> Self {
+        EventValue::Long(v as i64)
+    }
+}
+
+/// Unsigned 64 bit integers are converted to a `Long`. Values exceeding
+/// `i64::MAX` saturate.
+/// ```
+/// use android_logd_logger::EventValue;
+///
+/// assert_eq!(EventValue::from(42u64), EventValue::Long(42));
+/// assert_eq!(EventValue::from(u64::MAX), EventValue::Long(i64::MAX));
+/// ```
+impl From<u64> for EventValue {
+    fn from(v: u64) -> Self {
+        EventValue::Long(i64::try_from(v).unwrap_or(i64::MAX))
+    }
+}
+
+/// Booleans are converted to an `Int` of 0 or 1.
+/// ```
+/// use android_logd_logger::EventValue;
+///
+/// assert_eq!(EventValue::from(true), EventValue::Int(1));
+/// assert_eq!(EventValue::from(false), EventValue::Int(0));
+/// ```
+impl From<bool> for EventValue {
+    fn from(v: bool) -> Self {
+        EventValue::Int(v as i32)
+    }
+}
+
+/// Durations are converted to a `Long` of microseconds. Durations exceeding
+/// `i64::MAX` microseconds saturate.
+impl From<Duration> for EventValue {
+    fn from(v: Duration) -> Self {
+        EventValue::Long(i64::try_from(v.as_micros()).unwrap_or(i64::MAX))
+    }
+}
+
 impl<T> FromIterator<T> for EventValue
 where
     T: Into<EventValue>,
@@ -202,6 +424,43 @@ pub fn write_event_now<T: Into<EventValue>>(tag: EventTag, value: T) -> Result<(
     })
 }
 
+/// Write a timing event with the timestamp now to `Buffer::Events`
+///
+/// The value of the event is a list of `name` and the duration in
+/// microseconds.
+/// ```
+/// use android_logd_logger::write_timing_event;
+/// use std::time::Duration;
+/// android_logd_logger::builder().init();
+///
+/// write_timing_event(1, "startup", Duration::from_millis(120)).unwrap();
+/// ```
+pub fn write_timing_event(tag: EventTag, name: &str, duration: Duration) -> Result<(), Error> {
+    write_event_now(tag, (name, duration))
+}
+
+/// Run `f` and write a timing event with its execution time to `Buffer::Events`
+///
+/// The duration is measured with the monotonic clock. The event is written
+/// like with [`write_timing_event`] once `f` returns. Errors writing the
+/// event are ignored. Returns the result of `f`.
+/// ```
+/// use android_logd_logger::timed;
+/// android_logd_logger::builder().init();
+///
+/// let sum = timed(1, "sum", || (0..1000).sum::<u32>());
+/// assert_eq!(sum, 499500);
+/// ```
+pub fn timed<F, R>(tag: EventTag, name: &str, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let start = Instant::now();
+    let result = f();
+    write_timing_event(tag, name, start.elapsed()).ok();
+    result
+}
+
 /// Write an event with the timestamp now to buffer
 /// ```
 /// use android_logd_logger::{write_event_buffer_now, Buffer, Error, Event, EventValue};
@@ -250,15 +509,399 @@ pub fn write_event(event: &Event) -> Result<(), Error> {
 /// }).unwrap();
 /// ```
 pub fn write_event_buffer(log_buffer: Buffer, event: &Event) -> Result<(), Error> {
-    if event.value.serialized_size() > (LOGGER_ENTRY_MAX_LEN - 1 - 2 - 4 - 4 - 4) {
+    if !crate::writable() {
+        return Ok(());
+    }
+
+    let event = apply_event_options(event);
+
+    validate_size(&event)?;
+
+    send_event(log_buffer, &event)
+}
+
+/// Write an event to an explicit buffer without validating its size
+///
+/// This skips the computation of the serialized size of the event. The caller
+/// is responsible for keeping the event below the maximum entry length:
+/// oversized events are silently discarded by logd or the kernel instead of
+/// being reported with [`Error::EventSize`].
+/// ```
+/// use android_logd_logger::{write_event_unchecked, Buffer, Event};
+/// android_logd_logger::builder().init();
+///
+/// write_event_unchecked(Buffer::Events, &Event {
+///     timestamp: std::time::SystemTime::now(),
+///     tag: 1,
+///     value: 42.into(),
+/// }).unwrap();
+/// ```
+pub fn write_event_unchecked(log_buffer: Buffer, event: &Event) -> Result<(), Error> {
+    if !crate::writable() {
+        return Ok(());
+    }
+
+    send_event(log_buffer, &apply_event_options(event))
+}
+
+/// Write an event to an explicit buffer and wait until it is handed to logd
+///
+/// Events are usually written to a non blocking socket and discarded if logd
+/// is busy. This function sends the event on a dedicated blocking socket and
+/// returns an error if the event cannot be delivered to the kernel. The call
+/// blocks while logd is busy and opens a socket per event. Use it for
+/// critical events, e.g. audit events, only.
+///
+/// The call blocks at most for the timeout of
+/// [`WriteMode::Blocking`](crate::WriteMode::Blocking), or one second if the
+/// write mode is [`WriteMode::Discard`](crate::WriteMode::Discard), and fails
+/// with [`io::ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut) if logd does
+/// not accept the event in time. A zero timeout blocks without limit.
+/// ```
+/// use android_logd_logger::{write_event_blocking, Buffer, Event};
+/// android_logd_logger::builder().init();
+///
+/// write_event_blocking(Buffer::Security, &Event {
+///     timestamp: std::time::SystemTime::now(),
+///     tag: 1,
+///     value: "login".into(),
+/// }).unwrap();
+/// ```
+pub fn write_event_blocking(log_buffer: Buffer, event: &Event) -> Result<(), Error> {
+    if !crate::writable() {
+        return Ok(());
+    }
+
+    let event = apply_event_options(event);
+
+    validate_size(&event)?;
+
+    #[cfg(target_os = "android")]
+    crate::logd::write_event_blocking(log_buffer, &event)?;
+
+    #[cfg(not(target_os = "android"))]
+    print_event(log_buffer, &event);
+
+    Ok(())
+}
+
+/// Validate the size of events before writing them
+///
+/// The configured event options, e.g. the process name, are applied before
+/// the check like when writing. Returns the indices and errors of the events
+/// exceeding the maximum entry length.
+/// ```
+/// use android_logd_logger::{validate_events, Error, Event};
+/// use std::time::SystemTime;
+///
+/// let events = [
+///     Event { timestamp: SystemTime::now(), tag: 1, value: "ok".into() },
+///     Event { timestamp: SystemTime::now(), tag: 2, value: "x".repeat(10_000).as_str().into() },
+/// ];
+/// let errors = validate_events(&events).unwrap_err();
+/// assert_eq!(errors.len(), 1);
+/// assert!(matches!(errors[0], (1, Error::EventSize)));
+/// ```
+pub fn validate_events(events: &[Event]) -> Result<(), Vec<(usize, Error)>> {
+    let errors = events
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, event)| validate_size(&apply_event_options(event)).err().map(|e| (idx, e)))
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Fails if the event exceeds the maximum entry length with the header of
+/// buffer id, thread id, timestamp and tag or contains a list with more than
+/// 255 elements.
+fn validate_size(event: &Event) -> Result<(), Error> {
+    if event.value.serialized_size() > (max_entry_len() - 1 - 2 - 4 - 4 - 4) {
         return Err(Error::EventSize);
     }
+    event.value.validate_list_len()
+}
 
+/// Apply the event options configured in the builder.
+///
+/// The correlation id is prepended first so that the process name, if
+/// enabled, is the first element followed by the correlation id.
+fn apply_event_options(event: &Event) -> Cow<'_, Event> {
+    let mut event = Cow::Borrowed(event);
+    if crate::EVENT_CORRELATION.load(Ordering::Relaxed) {
+        if let Some(id) = crate::correlation_id() {
+            event = Cow::Owned(prepend_value(&event, EventValue::String(id)));
+        }
+    }
+    if crate::EVENT_INCLUDE_PROCESS.load(Ordering::Relaxed) {
+        let name = EventValue::String(process_name().to_string());
+        event = Cow::Owned(prepend_value(&event, name));
+    }
+    event
+}
+
+/// Send an event to logd or print it on non Android platforms.
+fn send_event(log_buffer: Buffer, event: &Event) -> Result<(), Error> {
     #[cfg(target_os = "android")]
-    crate::logd::write_event(log_buffer, event);
+    crate::logd::write_event(log_buffer, event)?;
 
     #[cfg(not(target_os = "android"))]
-    println!("buffer: {:?}, event: {:?}", log_buffer, event);
+    print_event(log_buffer, event);
 
     Ok(())
 }
+
+/// Print an event to stdout or as JSON to stderr if enabled.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn print_event(log_buffer: Buffer, event: &Event) {
+    if crate::EVENT_STDERR_JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", event_json(log_buffer, event));
+    } else {
+        println!("buffer: {:?}, event: {:?}", log_buffer, event);
+    }
+}
+
+/// Format an event as a JSON object.
+#[cfg(not(target_os = "android"))]
+fn event_json(log_buffer: Buffer, event: &Event) -> String {
+    let timestamp = event.timestamp.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let mut json = String::from("{\"buffer\":");
+    push_json_str(&mut json, &format!("{:?}", log_buffer));
+    json.push_str(&format!(
+        ",\"timestamp\":{}.{:09},\"tag\":{},\"value\":",
+        timestamp.as_secs(),
+        timestamp.subsec_nanos(),
+        event.tag
+    ));
+    push_json_value(&mut json, &event.value);
+    json.push('}');
+    json
+}
+
+/// Append `value` as JSON object with the type as key. Void is `null`.
+#[cfg(not(target_os = "android"))]
+fn push_json_value(json: &mut String, value: &EventValue) {
+    match value {
+        EventValue::Void => json.push_str("null"),
+        EventValue::Int(n) => json.push_str(&format!("{{\"int\":{}}}", n)),
+        EventValue::Long(n) => json.push_str(&format!("{{\"long\":{}}}", n)),
+        // JSON cannot represent NaN and infinity.
+        EventValue::Float(n) if !n.is_finite() => json.push_str("{\"float\":null}"),
+        EventValue::Float(n) => json.push_str(&format!("{{\"float\":{}}}", n)),
+        EventValue::String(s) => {
+            json.push_str("{\"string\":");
+            push_json_str(json, s);
+            json.push('}');
+        }
+        EventValue::List(values) => {
+            json.push_str("{\"list\":[");
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    json.push(',');
+                }
+                push_json_value(json, value);
+            }
+            json.push_str("]}");
+        }
+    }
+}
+
+/// Append `s` as quoted and escaped JSON string.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn push_json_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Name of the executable of this process.
+fn process_name() -> &'static str {
+    static PROCESS_NAME: OnceLock<String> = OnceLock::new();
+    PROCESS_NAME.get_or_init(|| {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_default()
+    })
+}
+
+/// Prepend `first` to the event value. Values that are not lists are wrapped
+/// into a list.
+fn prepend_value(event: &Event, first: EventValue) -> Event {
+    let value = match &event.value {
+        EventValue::List(values) => iter::once(first).chain(values.iter().cloned()).collect(),
+        EventValue::Void => EventValue::List(vec![first]),
+        value => EventValue::List(vec![first, value.clone()]),
+    };
+    Event {
+        timestamp: event.timestamp,
+        tag: event.tag,
+        value,
+    }
+}
+
+#[test]
+fn duration_micros() {
+    assert_eq!(EventValue::from(Duration::from_millis(3)), EventValue::Long(3000));
+    assert_eq!(EventValue::from(Duration::MAX), EventValue::Long(i64::MAX));
+}
+
+#[test]
+fn value_prepended() {
+    let name = EventValue::String(process_name().to_string());
+    let event = |value| Event {
+        timestamp: SystemTime::now(),
+        tag: 1,
+        value,
+    };
+
+    assert_eq!(
+        prepend_value(&event(EventValue::Void), name.clone()).value,
+        EventValue::List(vec![name.clone()])
+    );
+    assert_eq!(
+        prepend_value(&event(1.into()), name.clone()).value,
+        EventValue::List(vec![name.clone(), 1.into()])
+    );
+    assert_eq!(
+        prepend_value(&event((1, "a").into()), name.clone()).value,
+        EventValue::List(vec![name, 1.into(), "a".into()])
+    );
+
+    let full = prepend_value(&event(EventValue::List(vec![EventValue::Int(0); 255])), "name".into());
+    assert!(matches!(write_event_buffer(Buffer::Events, &full), Err(Error::EventSize)));
+}
+
+#[test]
+#[cfg(not(target_os = "android"))]
+fn json() {
+    let event = Event {
+        timestamp: std::time::UNIX_EPOCH + Duration::new(1, 5),
+        tag: 7,
+        value: EventValue::List(vec![1.into(), 2i64.into(), 1.5f32.into(), "a\"b\n".into(), EventValue::Void]),
+    };
+    assert_eq!(
+        event_json(Buffer::Events, &event),
+        r#"{"buffer":"Events","timestamp":1.000000005,"tag":7,"value":{"list":[{"int":1},{"long":2},{"float":1.5},{"string":"a\"b\n"},null]}}"#
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_value() {
+    let value = EventValue::List(vec![
+        1.into(),
+        EventValue::List(vec![2i64.into(), "a".into(), EventValue::List(vec![])]),
+        1.5f32.into(),
+        f32::NAN.into(),
+        EventValue::Void,
+    ]);
+    assert_eq!(
+        serde_json::Value::from(&value),
+        serde_json::json!([1, [2, "a", []], 1.5, null, null])
+    );
+}
+
+#[test]
+fn round_trip() {
+    let values = [
+        EventValue::Void,
+        EventValue::Int(-1),
+        EventValue::Long(i64::MIN),
+        EventValue::Float(2.5),
+        EventValue::String("hello".into()),
+        EventValue::List(vec![]),
+        EventValue::List(vec![1.into(), vec![EventValue::from("nested"), 2i64.into()].into()]),
+    ];
+    for order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+        for value in &values {
+            let mut bytes = value.encode(order);
+            assert_eq!(&EventValue::decode(&mut bytes, order).unwrap(), value);
+            assert!(bytes.is_empty());
+        }
+    }
+}
+
+#[test]
+fn decode_malformed() {
+    let order = ByteOrder::LittleEndian;
+    // Truncated int, string and list
+    for bytes in [&[0u8, 1, 2][..], &[2, 5, 0, 0, 0, b'a'], &[3, 2, 0, 1, 0, 0, 0]] {
+        assert!(matches!(EventValue::decode(&mut &bytes[..], order), Err(Error::Decode(_))));
+    }
+    // Unknown type tag
+    assert!(matches!(EventValue::decode(&mut &[9u8][..], order), Err(Error::Decode(_))));
+    // Nested too deep
+    let mut value = EventValue::Int(1);
+    for _ in 0..=MAX_LIST_DEPTH {
+        value = EventValue::List(vec![value]);
+    }
+    assert!(matches!(
+        EventValue::decode(&mut value.encode(order), order),
+        Err(Error::Decode(_))
+    ));
+}
+
+#[test]
+fn list_too_long() {
+    let list = EventValue::List(vec![EventValue::Int(0); 300]);
+    assert!(matches!(list.try_as_bytes(), Err(Error::EventSize)));
+
+    let nested = EventValue::List(vec![list.clone()]);
+    assert!(matches!(nested.try_as_bytes(), Err(Error::EventSize)));
+
+    let event = Event {
+        timestamp: SystemTime::now(),
+        tag: 1,
+        value: list,
+    };
+    assert!(matches!(write_event(&event), Err(Error::EventSize)));
+
+    let list = EventValue::List(vec![EventValue::Int(0); 255]);
+    assert_eq!(list.try_as_bytes().unwrap(), list.as_bytes());
+}
+
+#[test]
+fn serialized_size_matches() {
+    /// Xorshift generator for reproducible value trees.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn value(&mut self, depth: usize) -> EventValue {
+            match self.next() % if depth < MAX_LIST_DEPTH { 6 } else { 5 } {
+                0 => EventValue::Void,
+                1 => EventValue::Int(self.next() as i32),
+                2 => EventValue::Long(self.next() as i64),
+                3 => EventValue::Float(self.next() as f32),
+                4 => EventValue::String("x".repeat((self.next() % 64) as usize)),
+                _ => EventValue::List((0..self.next() % 8).map(|_| self.value(depth + 1)).collect()),
+            }
+        }
+    }
+
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..10_000 {
+        let value = rng.value(0);
+        assert_eq!(value.as_bytes().len(), value.serialized_size(), "{:?}", value);
+    }
+}
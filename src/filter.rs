@@ -0,0 +1,117 @@
+use env_logger::filter::{Builder, Filter};
+use log::LevelFilter;
+
+/// Filter directives.
+///
+/// The `Filter` built by `env_logger` is opaque. The directives are retained
+/// in order to allow incremental changes and to read them back.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Directives {
+    /// Module (or `None` for all modules) and level pairs.
+    directives: Vec<(Option<String>, LevelFilter)>,
+    /// Regular expression the message must match.
+    regex: Option<String>,
+}
+
+impl Directives {
+    /// Adds a directive. An existing directive for the same module is replaced.
+    pub(crate) fn insert(&mut self, module: Option<&str>, level: LevelFilter) {
+        match self.directives.iter_mut().find(|(m, _)| m.as_deref() == module) {
+            Some((_, l)) => *l = level,
+            None => self.directives.push((module.map(str::to_string), level)),
+        }
+    }
+
+    /// Parses the directives string in the same form as the `RUST_LOG`
+    /// environment variable and merges the result.
+    ///
+    /// This follows the parsing of `env_logger::filter::Builder::parse`.
+    pub(crate) fn parse(&mut self, spec: &str) {
+        let mut parts = spec.split('/');
+        let mods = parts.next();
+        let regex = parts.next();
+        if parts.next().is_some() {
+            eprintln!("warning: invalid logging spec '{}', ignoring it (too many '/'s)", spec);
+            self.regex = None;
+            return;
+        }
+
+        for s in mods.unwrap_or_default().split(',').map(str::trim) {
+            if s.is_empty() {
+                continue;
+            }
+            let mut parts = s.split('=');
+            let (level, module) = match (parts.next(), parts.next().map(str::trim), parts.next()) {
+                (Some(part0), None, None) => match part0.parse() {
+                    // A single level is a global fallback
+                    Ok(level) => (level, None),
+                    Err(_) => (LevelFilter::max(), Some(part0)),
+                },
+                (Some(part0), Some(""), None) => (LevelFilter::max(), Some(part0)),
+                (Some(part0), Some(part1), None) => match part1.parse() {
+                    Ok(level) => (level, Some(part0)),
+                    Err(_) => {
+                        eprintln!("warning: invalid logging spec '{}', ignoring it", part1);
+                        continue;
+                    }
+                },
+                _ => {
+                    eprintln!("warning: invalid logging spec '{}', ignoring it", s);
+                    continue;
+                }
+            };
+            self.insert(module, level);
+        }
+
+        self.regex = regex.map(str::to_string);
+    }
+
+    /// Module specific levels.
+    pub(crate) fn modules(&self) -> Vec<(String, LevelFilter)> {
+        self.directives
+            .iter()
+            .filter_map(|(module, level)| module.clone().map(|module| (module, *level)))
+            .collect()
+    }
+
+    /// Builds the `env_logger` filter.
+    pub(crate) fn build(&self) -> Filter {
+        let mut builder = Builder::default();
+        for (module, level) in &self.directives {
+            builder.filter(module.as_deref(), *level);
+        }
+        if let Some(regex) = &self.regex {
+            builder.parse(&format!("/{}", regex));
+        }
+        builder.build()
+    }
+}
+
+#[test]
+fn parse() {
+    let mut directives = Directives::default();
+    directives.parse("info,foo=debug,bar,baz=invalid/re");
+    assert_eq!(
+        directives.directives,
+        vec![
+            (None, LevelFilter::Info),
+            (Some("foo".into()), LevelFilter::Debug),
+            (Some("bar".into()), LevelFilter::Trace),
+        ]
+    );
+    assert_eq!(directives.regex.as_deref(), Some("re"));
+    assert_eq!(directives.build().filter(), LevelFilter::Trace);
+}
+
+#[test]
+fn insert_merges() {
+    let mut directives = Directives::default();
+    directives.parse("warn,foo=debug");
+    directives.insert(Some("foo"), LevelFilter::Error);
+    directives.insert(Some("bar"), LevelFilter::Info);
+    assert_eq!(
+        directives.modules(),
+        vec![("foo".into(), LevelFilter::Error), ("bar".into(), LevelFilter::Info)]
+    );
+    assert_eq!(directives.build().filter(), LevelFilter::Info);
+}
@@ -0,0 +1,83 @@
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+/// State shared between the future and the flushing thread.
+#[derive(Default)]
+struct State {
+    result: Option<io::Result<()>>,
+    waker: Option<Waker>,
+}
+
+/// Future completing with the result of a function run on a separate thread.
+pub(crate) struct FlushFuture {
+    state: Arc<Mutex<State>>,
+}
+
+/// Run `f` on a separate thread and return a future that completes with its result.
+pub(crate) fn spawn<F>(f: F) -> FlushFuture
+where
+    F: FnOnce() -> io::Result<()> + Send + 'static,
+{
+    let state = Arc::new(Mutex::new(State::default()));
+
+    let thread_state = state.clone();
+    let spawned = thread::Builder::new().name("logd-flush".into()).spawn(move || {
+        let result = f();
+        let mut state = thread_state.lock();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+
+    if let Err(e) = spawned {
+        state.lock().result = Some(Err(e));
+    }
+
+    FlushFuture { state }
+}
+
+impl Future for FlushFuture {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[test]
+fn completes() {
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = spawn(|| Ok(()));
+    loop {
+        match Pin::new(&mut future).poll(&mut cx) {
+            Poll::Ready(result) => break assert!(result.is_ok()),
+            Poll::Pending => thread::park(),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    log_record,
+    logging_iterator::{limit_chunks, NewlineScaledChunkIterator},
+    max_chunks, max_entry_len, thread, Buffer, Error, Priority, Record, TimestampFormat,
+};
+use std::{fmt::Write, process, time::SystemTime};
+
+/// Number of bytes per line of the dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// Logs `data` as a hexdump to [`Buffer::Main`].
+///
+/// Each line contains the offset, the hex values and the printable ASCII
+/// representation of 16 bytes like `hexdump -C`. Dumps exceeding the entry
+/// limit are split at line boundaries into multiple entries.
+///
+/// # Examples
+///
+/// ```
+/// # use android_logd_logger::Priority;
+///
+/// android_logd_logger::log_hexdump("protocol", Priority::Debug, b"Hello World\n").unwrap();
+/// ```
+pub fn log_hexdump(tag: &str, priority: Priority, data: &[u8]) -> Result<(), Error> {
+    if !crate::writable() {
+        return Ok(());
+    }
+
+    // Header of the logd entry, priority and the terminating zeros of the tag and message.
+    const OVERHEAD: usize = 12 + 1 + 2;
+
+    let dump = hexdump(data);
+    let max_len = max_entry_len().saturating_sub(OVERHEAD + tag.len());
+    let timestamp = SystemTime::now();
+
+    let chunks = NewlineScaledChunkIterator::new(&dump, max_len);
+    for chunk in limit_chunks(chunks, max_chunks()) {
+        let record = Record {
+            timestamp,
+            pid: process::id(),
+            thread_id: thread::id() as u16,
+            buffer_id: Buffer::Main,
+            tag,
+            priority,
+            message: chunk.trim_end_matches('\n'),
+            uid: None,
+        };
+        log_record(&record, &TimestampFormat::default())?;
+    }
+    Ok(())
+}
+
+/// Formats `data` in the canonical hex and ASCII format.
+fn hexdump(data: &[u8]) -> String {
+    let mut dump = String::with_capacity((data.len() / BYTES_PER_LINE + 1) * 78);
+    for (line, bytes) in data.chunks(BYTES_PER_LINE).enumerate() {
+        write!(dump, "{:08x} ", line * BYTES_PER_LINE).ok();
+        for idx in 0..BYTES_PER_LINE {
+            if idx % 8 == 0 {
+                dump.push(' ');
+            }
+            match bytes.get(idx) {
+                Some(byte) => write!(dump, "{:02x} ", byte).ok(),
+                None => write!(dump, "   ").ok(),
+            };
+        }
+        dump.push_str(" |");
+        dump.extend(
+            bytes
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }),
+        );
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+#[test]
+fn format() {
+    assert_eq!(hexdump(&[]), "");
+    assert_eq!(
+        hexdump(b"Hello World\n\x00\x01\x02\x03 and more"),
+        "00000000  48 65 6c 6c 6f 20 57 6f  72 6c 64 0a 00 01 02 03  |Hello World.....|\n\
+         00000010  20 61 6e 64 20 6d 6f 72  65                       | and more|\n"
+    );
+}
@@ -2,32 +2,330 @@
 
 #![deny(missing_docs)]
 
-use env_logger::filter::Builder as FilterBuilder;
-use log::{set_boxed_logger, LevelFilter, SetLoggerError};
+use filter::Directives;
+use log::{set_boxed_logger, Level, LevelFilter, SetLoggerError};
 use logger::Configuration;
 use parking_lot::RwLock;
-use std::{fmt, io, sync::Arc, time::SystemTime};
+use std::{
+    borrow::Cow,
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 use thiserror::Error;
 
+#[cfg(feature = "atrace")]
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+mod atrace;
+mod byte_order;
+#[cfg(feature = "pmsg-compress")]
+mod compress;
+mod correlation;
+mod counter;
+mod custom_buffers;
+pub mod debug;
+mod diagnostics;
+mod event_tags;
+mod event_template;
 mod events;
+mod filter;
+#[cfg(feature = "async")]
+mod flush_future;
+mod hexdump;
 #[allow(dead_code)]
 #[cfg(not(target_os = "windows"))]
 mod logd;
 mod logger;
-#[cfg(target_os = "android")]
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
 mod logging_iterator;
-#[cfg(target_os = "android")]
+mod macros;
+mod panic_hook;
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
 mod pmsg;
+mod preinit;
+mod rate_limit;
+mod repeats;
+mod ring_buffer;
+mod stats;
+#[cfg(not(target_os = "windows"))]
+mod stream;
 mod thread;
 
+pub use byte_order::ByteOrder;
+#[cfg(feature = "pmsg-compress")]
+pub use compress::{decompress, COMPRESSED_MARKER};
+pub use correlation::{clear_correlation_id, correlation_id, set_correlation_id, with_correlation_id, ContextFormat};
+pub use counter::write_counter_event;
+pub use custom_buffers::CustomBuffers;
+pub use diagnostics::SELF_DIAGNOSTICS_EVENT_TAG;
+pub use event_tags::*;
+pub use event_template::EventTemplate;
 pub use events::*;
+pub use hexdump::log_hexdump;
+pub use panic_hook::{install_panic_hook, install_panic_hook_with_backtrace, uninstall_panic_hook};
+
+/// Chunking of long messages.
+pub mod chunk {
+    pub use crate::logging_iterator::NewlineScaledChunkIterator;
+}
 
 /// Logger configuration handle.
 pub use logger::Logger;
 
+pub use logger::Batch;
+pub use preinit::capture_preinit;
+pub use ring_buffer::CapturedRecord;
+
 /// Max log entry len.
 const LOGGER_ENTRY_MAX_LEN: usize = 5 * 1024;
 
+/// Range of accepted values for [`Builder::max_entry_len`].
+const LOGGER_ENTRY_MAX_LEN_RANGE: std::ops::RangeInclusive<usize> = 1024..=u16::MAX as usize;
+
+/// Configured max log entry len. See [`Builder::max_entry_len`].
+static MAX_ENTRY_LEN: AtomicUsize = AtomicUsize::new(LOGGER_ENTRY_MAX_LEN);
+
+/// Returns the configured max log entry len.
+fn max_entry_len() -> usize {
+    MAX_ENTRY_LEN.load(Ordering::Relaxed)
+}
+
+/// Default of the maximum number of chunks a message is split into.
+const DEFAULT_MAX_CHUNKS: usize = 256;
+
+/// Maximum number of chunks a message is split into. See [`Builder::max_chunks`].
+static MAX_CHUNKS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CHUNKS);
+
+/// Returns the configured maximum number of chunks a message is split into.
+fn max_chunks() -> usize {
+    MAX_CHUNKS.load(Ordering::Relaxed)
+}
+
+/// Global switch of all output. See [`set_enabled`].
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables all output of this crate.
+///
+/// If disabled, records logged via the `log` crate and the [`Logger`],
+/// records written with [`log`](crate::log) and events are discarded
+/// without further processing. In contrast to the filter this also applies
+/// to the explicit APIs. Enabled by default.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::builder().init();
+///
+/// android_logd_logger::set_enabled(false);
+/// log::error!("discarded");
+/// android_logd_logger::write_event_now(1, "discarded").unwrap();
+/// android_logd_logger::set_enabled(true);
+/// ```
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns false if all output is disabled with [`set_enabled`].
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns false if all output is disabled or if called from the error
+/// handler. See [`Builder::on_error`].
+fn writable() -> bool {
+    enabled() && !logger::dropped_by_error_handler()
+}
+
+/// Encode and validate but do not write. See [`Builder::dry_run`].
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if records and events are only encoded and validated.
+fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Format of timestamps in records printed to stderr. See
+/// [`Builder::timestamp_format`] and [`Builder::local_time`].
+#[derive(Clone, Default)]
+pub(crate) struct TimestampFormat {
+    pub(crate) format: Option<Arc<time::format_description::OwnedFormatItem>>,
+    pub(crate) local: bool,
+}
+
+impl TimestampFormat {
+    /// Parse a format description of the `time` crate.
+    fn parse(format: &str) -> Result<Arc<time::format_description::OwnedFormatItem>, Error> {
+        time::format_description::parse_owned::<2>(format)
+            .map(Arc::new)
+            .map_err(|e| Error::Timestamp(e.to_string()))
+    }
+
+    /// Format `timestamp` in UTC or local time.
+    fn format(&self, timestamp: SystemTime) -> Result<String, Error> {
+        let timestamp = timestamp
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| Error::Timestamp(e.to_string()))
+            .and_then(|ts| {
+                time::OffsetDateTime::from_unix_timestamp_nanos(ts.as_nanos() as i128)
+                    .map_err(|e| Error::Timestamp(e.to_string()))
+            })?;
+        let offset = if self.local {
+            *LOCAL_OFFSET.read()
+        } else {
+            time::UtcOffset::UTC
+        };
+        format_date_time(timestamp.to_offset(offset), self.format.as_deref())
+    }
+}
+
+/// Offset of the local time zone determined in [`Builder::try_init`]. See
+/// [`Builder::local_time`].
+static LOCAL_OFFSET: RwLock<time::UtcOffset> = parking_lot::const_rwlock(time::UtcOffset::UTC);
+
+/// Coloring of priorities in records printed to stderr. See [`Builder::color`].
+static COLOR: RwLock<ColorMode> = parking_lot::const_rwlock(ColorMode::Never);
+
+/// Returns true if priorities printed to stderr are colored.
+fn stderr_color() -> bool {
+    use std::io::IsTerminal;
+
+    match *COLOR.read() {
+        ColorMode::Auto => io::stderr().is_terminal(),
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+    }
+}
+
+/// Returns true if priorities written to the target writer are colored.
+fn target_color() -> bool {
+    *COLOR.read() == ColorMode::Always
+}
+
+/// Width of tags in records printed to stderr. Zero keeps the tags as is. See [`Builder::tag_width`].
+static TAG_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Representation of priorities in records printed to stderr. See [`Builder::priority_display`].
+static PRIORITY_DISPLAY: RwLock<PriorityDisplayStyle> = parking_lot::const_rwlock(PriorityDisplayStyle::Letter);
+
+/// Writer of formatted records. Records are printed to stderr if unset. See [`Builder::target_writer`].
+static TARGET_WRITER: parking_lot::Mutex<Option<Box<dyn io::Write + Send>>> = parking_lot::const_mutex(None);
+
+/// Writes `record` formatted to the target writer. Returns false if no
+/// target writer is set.
+fn write_target(record: &Record, timestamps: &TimestampFormat) -> Result<bool, Error> {
+    use io::Write;
+
+    let mut writer = TARGET_WRITER.lock();
+    match writer.as_mut() {
+        Some(writer) => {
+            writeln!(writer, "{}", format_line(record, target_color(), timestamps)?)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Flushes the target writer or stderr if unset.
+fn flush_target() -> io::Result<()> {
+    use io::Write;
+
+    match TARGET_WRITER.lock().as_mut() {
+        Some(writer) => writer.flush(),
+        None => io::stderr().flush(),
+    }
+}
+
+/// Print records to stderr if logd is unavailable. See [`Builder::stderr_fallback`].
+static STDERR_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Prepend the process name to events. See [`Builder::event_include_process`].
+static EVENT_INCLUDE_PROCESS: AtomicBool = AtomicBool::new(false);
+
+/// Prepend the correlation id to events. See [`Builder::event_correlation`].
+static EVENT_CORRELATION: AtomicBool = AtomicBool::new(false);
+
+/// Parse a priority prefix of forwarded messages. See [`Builder::parse_embedded_priority`].
+static PARSE_EMBEDDED_PRIORITY: AtomicBool = AtomicBool::new(false);
+
+/// Returns the priority and the remainder of a message starting with a
+/// `logcat` priority letter and a slash, e.g. `E/`.
+fn embedded_priority(message: &str) -> Option<(Priority, &str)> {
+    let priority = match message.as_bytes().get(..2)? {
+        b"V/" => Priority::Verbose,
+        b"D/" => Priority::Debug,
+        b"I/" => Priority::Info,
+        b"W/" => Priority::Warn,
+        b"E/" => Priority::Error,
+        b"F/" | b"A/" => Priority::_Fatal,
+        _ => return None,
+    };
+    Some((priority, &message[2..]))
+}
+
+/// Priority used for records without a level. See [`Builder::default_priority`].
+static DEFAULT_PRIORITY: RwLock<Option<Priority>> = parking_lot::const_rwlock(None);
+
+/// Replaces a missing priority with the configured default priority.
+fn default_priority(priority: Priority) -> Priority {
+    match (priority, *DEFAULT_PRIORITY.read()) {
+        (Priority::_Unknown | Priority::_Default, Some(default)) => default,
+        (priority, _) => priority,
+    }
+}
+
+/// Byte order of encoded records and events. See [`Builder::byte_order`].
+static BYTE_ORDER: RwLock<ByteOrder> = parking_lot::const_rwlock(ByteOrder::LittleEndian);
+
+/// Returns the configured byte order.
+fn byte_order() -> ByteOrder {
+    *BYTE_ORDER.read()
+}
+
+/// Print events as JSON to stderr on non Android platforms. See [`Builder::event_stderr_json`].
+#[cfg(not(target_os = "android"))]
+static EVENT_STDERR_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Print records as JSON on non Android platforms. See [`Builder::format_json`].
+#[cfg(not(target_os = "android"))]
+static FORMAT_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Unit of the fractional part of event timestamps. See [`Builder::event_timestamp_unit`].
+static EVENT_TIMESTAMP_UNIT: RwLock<TimestampUnit> = parking_lot::const_rwlock(TimestampUnit::Nanos);
+
+/// Returns the configured unit of the fractional part of event timestamps.
+fn event_timestamp_unit() -> TimestampUnit {
+    *EVENT_TIMESTAMP_UNIT.read()
+}
+
+/// Behaviour of writes to a busy logd socket. See [`Builder::write_mode`].
+static WRITE_MODE: RwLock<WriteMode> = parking_lot::const_rwlock(WriteMode::Discard);
+
+/// Returns the configured write mode.
+#[cfg_attr(target_os = "windows", allow(unused))]
+fn write_mode() -> WriteMode {
+    *WRITE_MODE.read()
+}
+
+/// Minimum interval between reconnect attempts to logd. See [`Builder::reconnect_interval`].
+static RECONNECT_INTERVAL: RwLock<Duration> = parking_lot::const_rwlock(Duration::ZERO);
+
+/// Returns the configured minimum interval between reconnect attempts.
+fn reconnect_interval() -> Duration {
+    *RECONNECT_INTERVAL.read()
+}
+
+/// Require credentials to be passed to logd. See [`Builder::require_passcred`].
+static REQUIRE_PASSCRED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if records are only sent on sockets with `SO_PASSCRED` enabled.
+#[cfg_attr(target_os = "windows", allow(unused))]
+fn require_passcred() -> bool {
+    REQUIRE_PASSCRED.load(Ordering::Relaxed)
+}
+
 /// Error
 #[derive(Error, Debug)]
 pub enum Error {
@@ -37,13 +335,38 @@ pub enum Error {
     /// The supplied event data exceed the maximum length
     #[error("Event exceeds maximum size")]
     EventSize,
+    /// The event tag name is not registered
+    #[error("Unknown event tag: {0}")]
+    UnknownEventTag(String),
+    /// Malformed line of an `event-log-tags` file with the line number
+    #[error("Malformed event tag definition in line {0}: {1}")]
+    EventTagDefinition(usize, String),
+    /// The priority name is unknown
+    #[error("Unknown priority: {0}")]
+    UnknownPriority(String),
+    /// The buffer name is unknown
+    #[error("Unknown buffer: {0}")]
+    UnknownBuffer(String),
+    /// The encoded log record exceeds the maximum length
+    #[error("Record exceeds maximum size")]
+    RecordSize,
     /// Timestamp error
     #[error("Timestamp error: {0}")]
     Timestamp(String),
+    /// Malformed encoded record or event
+    #[error("Decode error: {0}")]
+    Decode(String),
+    /// Credentials are not passed on the logd socket. See [`Builder::require_passcred`].
+    #[error("SO_PASSCRED is not enabled on the logd socket")]
+    Passcred,
+    /// Decompression error
+    #[cfg(feature = "pmsg-compress")]
+    #[error("Decompression error: {0}")]
+    Decompress(String),
 }
 
 /// Log priority as defined by logd
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Priority {
     /// For internal logd use only
@@ -76,7 +399,48 @@ pub enum Priority {
 
 impl std::fmt::Display for Priority {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let c = match self {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// Parses the letters `V`, `D`, `I`, `W` and `E` and the words `verbose`,
+/// `debug`, `info`, `warn` and `error` ignoring case. The internal
+/// priorities are rejected.
+///
+/// ```
+/// # use android_logd_logger::Priority;
+///
+/// assert!(matches!("W".parse::<Priority>(), Ok(Priority::Warn)));
+/// assert!(matches!("Error".parse::<Priority>(), Ok(Priority::Error)));
+/// assert!("F".parse::<Priority>().is_err());
+/// ```
+impl std::str::FromStr for Priority {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Priority, Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "v" | "verbose" => Ok(Priority::Verbose),
+            "d" | "debug" => Ok(Priority::Debug),
+            "i" | "info" => Ok(Priority::Info),
+            "w" | "warn" => Ok(Priority::Warn),
+            "e" | "error" => Ok(Priority::Error),
+            _ => Err(Error::UnknownPriority(s.to_string())),
+        }
+    }
+}
+
+impl Priority {
+    /// Letter of the priority as printed by `logcat`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Priority;
+    ///
+    /// assert_eq!(Priority::Info.as_char(), 'I');
+    /// ```
+    pub fn as_char(&self) -> char {
+        match self {
             Priority::_Unknown => 'U',
             Priority::_Default | Priority::Debug => 'D',
             Priority::Verbose => 'V',
@@ -85,8 +449,81 @@ impl std::fmt::Display for Priority {
             Priority::Error => 'E',
             Priority::_Fatal => 'F',
             Priority::_Silent => 'S',
-        };
-        f.write_str(&c.to_string())
+        }
+    }
+
+    /// Maps an optional `log` level to a priority or `default` if there is no level.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Priority;
+    ///
+    /// assert!(matches!(Priority::from_level_or(Some(log::Level::Warn), Priority::Info), Priority::Warn));
+    /// assert!(matches!(Priority::from_level_or(None, Priority::Info), Priority::Info));
+    /// ```
+    pub fn from_level_or(level: Option<log::Level>, default: Priority) -> Priority {
+        level.map(Priority::from).unwrap_or(default)
+    }
+
+    /// Maps a syslog severity to a priority.
+    ///
+    /// Android has fewer priorities than syslog has severities. The mapping
+    /// is:
+    ///
+    /// | Severity                               | Priority |
+    /// |----------------------------------------|----------|
+    /// | 0 (emergency), 1 (alert), 2 (critical) | fatal    |
+    /// | 3 (error)                              | error    |
+    /// | 4 (warning)                            | warn     |
+    /// | 5 (notice), 6 (informational)          | info     |
+    /// | 7 (debug)                              | debug    |
+    /// | above 7                                | verbose  |
+    ///
+    /// Notice is mapped to info because Android has no priority between info
+    /// and warn.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Priority;
+    ///
+    /// assert!(matches!(Priority::from_syslog_severity(3), Priority::Error));
+    /// assert!(matches!(Priority::from_syslog_severity(5), Priority::Info));
+    /// ```
+    pub fn from_syslog_severity(severity: u8) -> Priority {
+        match severity {
+            0..=2 => Priority::_Fatal,
+            3 => Priority::Error,
+            4 => Priority::Warn,
+            5 | 6 => Priority::Info,
+            7 => Priority::Debug,
+            _ => Priority::Verbose,
+        }
+    }
+
+    /// Maps a priority to a syslog severity.
+    ///
+    /// This is the inverse of [`from_syslog_severity`](Priority::from_syslog_severity).
+    /// Fatal maps to 2 (critical), info to 6 (informational) and all
+    /// priorities below info to 7 (debug).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Priority;
+    ///
+    /// assert_eq!(Priority::Warn.to_syslog_severity(), 4);
+    /// assert_eq!(Priority::Verbose.to_syslog_severity(), 7);
+    /// ```
+    pub fn to_syslog_severity(self) -> u8 {
+        match self {
+            Priority::_Fatal => 2,
+            Priority::Error => 3,
+            Priority::Warn => 4,
+            Priority::Info => 6,
+            Priority::_Unknown | Priority::_Default | Priority::Verbose | Priority::Debug | Priority::_Silent => 7,
+        }
     }
 }
 
@@ -103,7 +540,7 @@ impl From<log::Level> for Priority {
 }
 
 /// Log buffer ids
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Buffer {
     /// The main log buffer. This is the only log buffer available to apps.
@@ -124,6 +561,77 @@ pub enum Buffer {
     Custom(u8),
 }
 
+impl Buffer {
+    /// Returns the custom buffer registered as `name`.
+    ///
+    /// Custom buffers are registered with [`CustomBuffers::register`] or
+    /// [`Builder::custom_buffer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Buffer;
+    ///
+    /// android_logd_logger::builder().custom_buffer("my_buffer", 8).init();
+    ///
+    /// let buffer = Buffer::custom_named("my_buffer").expect("unknown buffer");
+    /// android_logd_logger::logd_info!(buffer, "hello my_buffer");
+    /// ```
+    pub fn custom_named(name: &str) -> Option<Buffer> {
+        CustomBuffers::get(name)
+    }
+}
+
+/// Parses the names used by `logcat -b`, e.g. `main` or `crash`. Numeric
+/// strings are parsed as [`Buffer::Custom`].
+///
+/// ```
+/// # use android_logd_logger::Buffer;
+///
+/// assert_eq!("crash".parse::<Buffer>().unwrap(), Buffer::Crash);
+/// assert_eq!("42".parse::<Buffer>().unwrap(), Buffer::Custom(42));
+/// assert!("unknown".parse::<Buffer>().is_err());
+/// ```
+impl std::str::FromStr for Buffer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Buffer, Error> {
+        match s {
+            "main" => Ok(Buffer::Main),
+            "radio" => Ok(Buffer::Radio),
+            "events" => Ok(Buffer::Events),
+            "system" => Ok(Buffer::System),
+            "crash" => Ok(Buffer::Crash),
+            "stats" => Ok(Buffer::Stats),
+            "security" => Ok(Buffer::Security),
+            s => s.parse().map(Buffer::Custom).map_err(|_| Error::UnknownBuffer(s.to_string())),
+        }
+    }
+}
+
+/// Formats the name used by `logcat -b` or the id of custom buffers.
+///
+/// ```
+/// # use android_logd_logger::Buffer;
+///
+/// assert_eq!(Buffer::Radio.to_string(), "radio");
+/// assert_eq!(Buffer::Custom(42).to_string(), "42");
+/// ```
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Buffer::Main => f.write_str("main"),
+            Buffer::Radio => f.write_str("radio"),
+            Buffer::Events => f.write_str("events"),
+            Buffer::System => f.write_str("system"),
+            Buffer::Crash => f.write_str("crash"),
+            Buffer::Stats => f.write_str("stats"),
+            Buffer::Security => f.write_str("security"),
+            Buffer::Custom(id) => write!(f, "{}", id),
+        }
+    }
+}
+
 impl From<Buffer> for u8 {
     fn from(b: Buffer) -> u8 {
         match b {
@@ -139,6 +647,99 @@ impl From<Buffer> for u8 {
     }
 }
 
+/// Clock used for timestamps
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Wall clock time (`CLOCK_REALTIME`)
+    #[default]
+    Realtime,
+    /// Time since boot including suspend (`CLOCK_BOOTTIME`)
+    Boottime,
+}
+
+/// Position of the version set with [`Builder::version_tag`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VersionPosition {
+    /// Append the version to the tag, e.g. `tag@1.2.3`
+    Tag,
+    /// Append the version to the message, e.g. `message [1.2.3]`
+    #[default]
+    Message,
+}
+
+/// Unit of the fractional part of event timestamps
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampUnit {
+    /// Nanoseconds like the timestamp of log records
+    #[default]
+    Nanos,
+    /// Microseconds
+    Micros,
+}
+
+/// Representation of the priority in records printed to stderr
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PriorityDisplayStyle {
+    /// Upper case letter like `logcat`, e.g. `I`
+    #[default]
+    Letter,
+    /// Lower case letter, e.g. `i`
+    LowerLetter,
+    /// Numeric value as defined by logd, e.g. `4`
+    Numeric,
+    /// Name, e.g. `INFO`
+    Word,
+}
+
+impl PriorityDisplayStyle {
+    /// Formats `priority` in this style.
+    fn format(self, priority: Priority) -> String {
+        match self {
+            PriorityDisplayStyle::Letter => priority.to_string(),
+            PriorityDisplayStyle::LowerLetter => priority.to_string().to_lowercase(),
+            PriorityDisplayStyle::Numeric => (priority as u8).to_string(),
+            PriorityDisplayStyle::Word => match priority {
+                Priority::_Unknown => "UNKNOWN",
+                Priority::_Default => "DEFAULT",
+                Priority::Verbose => "VERBOSE",
+                Priority::Debug => "DEBUG",
+                Priority::Info => "INFO",
+                Priority::Warn => "WARN",
+                Priority::Error => "ERROR",
+                Priority::_Fatal => "FATAL",
+                Priority::_Silent => "SILENT",
+            }
+            .to_string(),
+        }
+    }
+}
+
+/// Behaviour of writes to logd if the socket is busy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Discard the record or event
+    #[default]
+    Discard,
+    /// Block up to `timeout` and fail if the write does not complete. A zero
+    /// timeout blocks without limit.
+    Blocking {
+        /// Maximum time to wait for the socket
+        timeout: Duration,
+    },
+}
+
+/// Coloring of the priority in records printed to stderr
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Color if stderr is a terminal
+    Auto,
+    /// Always color, also if the output is not a terminal
+    Always,
+    /// Never color
+    #[default]
+    Never,
+}
+
 /// Tag mode
 #[derive(Debug, Default, Clone)]
 enum TagMode {
@@ -153,19 +754,53 @@ enum TagMode {
     Custom(String),
 }
 
+/// Tag mode of a logger. See [`Logger::tag_mode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagModeDescriptor {
+    /// The target of a record is the tag
+    Target,
+    /// The root module of the target of a record is the tag
+    TargetStrip,
+    /// Fixed tag
+    Custom(String),
+}
+
+impl From<&TagMode> for TagModeDescriptor {
+    fn from(mode: &TagMode) -> Self {
+        match mode {
+            TagMode::Target => TagModeDescriptor::Target,
+            TagMode::TargetStrip => TagModeDescriptor::TargetStrip,
+            TagMode::Custom(tag) => TagModeDescriptor::Custom(tag.clone()),
+        }
+    }
+}
+
 /// Logging record structure
 ///
 /// We build this structure in the [`Logger`] per `log()` call and pass
 /// consistent timestamps and other information to both the `logd` and the
 /// `pmsg` device without paying the price for system calls twice.
+#[derive(Clone, Copy)]
 struct Record<'tag, 'msg> {
     timestamp: SystemTime,
-    pid: u16,
+    pid: u32,
     thread_id: u16,
     buffer_id: Buffer,
     tag: &'tag str,
     priority: Priority,
     message: &'msg str,
+    /// UID the record is attributed to. `None` attributes the record to the
+    /// UID of the process.
+    uid: Option<u32>,
+}
+
+/// Returns the first component of a NUL separated command line.
+fn cmdline_name(cmdline: &[u8]) -> Option<String> {
+    cmdline
+        .split(|&b| b == 0)
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
 }
 
 /// Returns a default [`Builder`] for configuration and initialization of logging.
@@ -184,21 +819,139 @@ pub fn builder() -> Builder {
 /// The builder is used to initialize the logging framework for later use.
 /// It provides
 pub struct Builder {
-    filter: FilterBuilder,
+    filter: Directives,
     tag: TagMode,
     prepend_module: bool,
+    format: Option<logger::Formatter>,
     pstore: bool,
     buffer: Option<Buffer>,
+    level_routes: Vec<logger::LevelRoute>,
+    mirror_buffers: Vec<Buffer>,
+    stats_event: Option<(EventTag, Duration)>,
+    dry_run: bool,
+    stderr_fallback: bool,
+    target_writer: Option<Box<dyn io::Write + Send>>,
+    default_priority: Option<Priority>,
+    event_include_process: bool,
+    parse_embedded_priority: bool,
+    event_correlation: bool,
+    event_timestamp_unit: TimestampUnit,
+    #[cfg(not(target_os = "android"))]
+    event_stderr_json: bool,
+    #[cfg(not(target_os = "android"))]
+    format_json: bool,
+    max_entry_len: usize,
+    max_chunks: usize,
+    allow_self_logs: bool,
+    embed_priority: bool,
+    strip_ansi: bool,
+    strip_bom: bool,
+    context_format: Option<ContextFormat>,
+    log_shutdown: bool,
+    version: Option<&'static str>,
+    version_position: VersionPosition,
+    #[cfg(feature = "kv")]
+    kv_event: Option<EventTag>,
+    #[cfg(feature = "kv")]
+    render_kv: bool,
+    #[cfg(feature = "kv")]
+    honor_ts_field: bool,
+    #[cfg(feature = "pmsg-compress")]
+    pmsg_compress: bool,
+    #[cfg(feature = "atrace")]
+    atrace: bool,
+    pmsg_clock: ClockSource,
+    pstore_min_level: LevelFilter,
+    byte_order: ByteOrder,
+    ring_buffer: Option<usize>,
+    rate_limit: Option<(u32, Duration)>,
+    collapse_repeats: bool,
+    on_error: Option<logger::ErrorHandler>,
+    tag_width: usize,
+    color: ColorMode,
+    timestamp_format: TimestampFormat,
+    priority_display: PriorityDisplayStyle,
+    flush_timeout: Option<Duration>,
+    lockfree_hotpath: bool,
+    cache_thread_id: bool,
+    #[cfg(not(target_os = "windows"))]
+    stream_collector: Option<(std::path::PathBuf, bool)>,
+    reconnect_interval: Duration,
+    require_passcred: bool,
+    write_mode: WriteMode,
+    self_diagnostics_buffer: Option<Buffer>,
+    custom_buffers: Vec<(String, u8)>,
+    #[cfg(target_os = "android")]
+    buffer_sockets: Vec<(Buffer, std::path::PathBuf)>,
 }
 
 impl Default for Builder {
     fn default() -> Self {
         Self {
-            filter: FilterBuilder::default(),
+            filter: Directives::default(),
             tag: TagMode::default(),
             prepend_module: false,
+            format: None,
             pstore: true,
             buffer: None,
+            level_routes: Vec::new(),
+            mirror_buffers: Vec::new(),
+            stats_event: None,
+            dry_run: false,
+            stderr_fallback: false,
+            target_writer: None,
+            default_priority: None,
+            event_include_process: false,
+            parse_embedded_priority: false,
+            event_correlation: false,
+            event_timestamp_unit: TimestampUnit::default(),
+            #[cfg(not(target_os = "android"))]
+            event_stderr_json: false,
+            #[cfg(not(target_os = "android"))]
+            format_json: false,
+            max_entry_len: LOGGER_ENTRY_MAX_LEN,
+            max_chunks: DEFAULT_MAX_CHUNKS,
+            allow_self_logs: false,
+            embed_priority: false,
+            strip_ansi: false,
+            strip_bom: false,
+            context_format: None,
+            log_shutdown: false,
+            version: None,
+            version_position: VersionPosition::default(),
+            #[cfg(feature = "kv")]
+            kv_event: None,
+            #[cfg(feature = "kv")]
+            render_kv: false,
+            #[cfg(feature = "kv")]
+            honor_ts_field: false,
+            #[cfg(feature = "pmsg-compress")]
+            pmsg_compress: false,
+            #[cfg(feature = "atrace")]
+            atrace: false,
+            pmsg_clock: ClockSource::default(),
+            pstore_min_level: LevelFilter::Trace,
+            byte_order: ByteOrder::default(),
+            ring_buffer: None,
+            rate_limit: None,
+            collapse_repeats: false,
+            on_error: None,
+            tag_width: 0,
+            color: ColorMode::Never,
+            timestamp_format: TimestampFormat::default(),
+            priority_display: PriorityDisplayStyle::default(),
+            flush_timeout: None,
+            lockfree_hotpath: false,
+            cache_thread_id: true,
+            #[cfg(not(target_os = "windows"))]
+            stream_collector: None,
+            reconnect_interval: Duration::ZERO,
+            require_passcred: false,
+            write_mode: WriteMode::Discard,
+            self_diagnostics_buffer: None,
+            custom_buffers: Vec::new(),
+            #[cfg(target_os = "android")]
+            buffer_sockets: Vec::new(),
         }
     }
 }
@@ -241,184 +994,1512 @@ impl Builder {
         self
     }
 
-    /// Use a specific log tag. If no tag is set the module path
-    /// is used as tag (if present).
+    /// Writes records of `level` to `buffer` instead of the buffer of the logger.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use android_logd_logger::Builder;
+    /// # use android_logd_logger::{Buffer, Builder};
+    /// # use log::Level;
     ///
     /// let mut builder = Builder::new();
-    ///
-    /// builder.tag("foo")
-    ///     .init();
+    /// builder.route_level(Level::Warn, Buffer::System).init();
     /// ```
-    pub fn tag(&mut self, tag: &str) -> &mut Self {
-        self.tag = TagMode::Custom(tag.to_string());
+    pub fn route_level(&mut self, level: Level, buffer: Buffer) -> &mut Self {
+        self.level_routes.push(logger::LevelRoute {
+            level,
+            buffer,
+            additional: false,
+        });
         self
     }
 
-    /// Use the target string as tag
+    /// Writes each record to `buffers` in addition to the buffer of the logger.
+    ///
+    /// The record is serialized and sent once per buffer with identical
+    /// timestamp, pid and thread id. Every buffer costs an additional socket
+    /// write per record. The record is written to the pstore and the ring
+    /// buffer only once.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use android_logd_logger::Builder;
+    /// # use android_logd_logger::{Buffer, Builder};
     ///
     /// let mut builder = Builder::new();
-    /// builder.tag_target().init();
+    /// builder.mirror_buffers(&[Buffer::Security]).init();
     /// ```
-    pub fn tag_target(&mut self) -> &mut Self {
-        self.tag = TagMode::Target;
+    pub fn mirror_buffers(&mut self, buffers: &[Buffer]) -> &mut Self {
+        self.mirror_buffers = buffers.to_vec();
         self
     }
 
-    /// Use the target string as tag and strip off ::.*
+    /// Writes records of `level` to `buffer` in addition to the buffer of the logger.
+    ///
+    /// The record is written to the pstore and the ring buffer only once.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use android_logd_logger::Builder;
+    /// # use android_logd_logger::{Buffer, Builder};
+    /// # use log::Level;
     ///
     /// let mut builder = Builder::new();
-    /// builder.tag_target_strip().init();
+    /// builder.route_level_additional(Level::Error, Buffer::Crash).init();
     /// ```
-    pub fn tag_target_strip(&mut self) -> &mut Self {
-        self.tag = TagMode::TargetStrip;
+    pub fn route_level_additional(&mut self, level: Level, buffer: Buffer) -> &mut Self {
+        self.level_routes.push(logger::LevelRoute {
+            level,
+            buffer,
+            additional: true,
+        });
         self
     }
 
-    /// Prepend module to log message.
+    /// Registers the custom buffer `id` as `name`.
     ///
-    /// If set true the Rust module path is prepended to the log message.
+    /// The buffer is resolved with [`Buffer::custom_named`] after the
+    /// initialization.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use android_logd_logger::Builder;
+    /// # use android_logd_logger::{Buffer, Builder};
     ///
     /// let mut builder = Builder::new();
-    /// builder.prepend_module(true).init();
+    /// builder.custom_buffer("my_buffer", 8).init();
+    ///
+    /// assert!(matches!(Buffer::custom_named("my_buffer"), Some(Buffer::Custom(8))));
     /// ```
-    pub fn prepend_module(&mut self, prepend_module: bool) -> &mut Self {
-        self.prepend_module = prepend_module;
+    pub fn custom_buffer(&mut self, name: &str, id: u8) -> &mut Self {
+        self.custom_buffers.push((name.to_string(), id));
         self
     }
 
-    /// Adds a directive to the filter for a specific module.
+    /// Use a specific log tag. If no tag is set the module path
+    /// is used as tag (if present).
     ///
     /// # Examples
     ///
-    /// Only include messages for warning and above for logs in `path::to::module`:
-    ///
     /// ```
-    /// # use log::LevelFilter;
     /// # use android_logd_logger::Builder;
     ///
     /// let mut builder = Builder::new();
     ///
-    /// builder.filter_module("path::to::module", LevelFilter::Info).init();
+    /// builder.tag("foo")
+    ///     .init();
     /// ```
-    pub fn filter_module(&mut self, module: &str, level: LevelFilter) -> &mut Self {
-        self.filter.filter_module(module, level);
+    pub fn tag(&mut self, tag: &str) -> &mut Self {
+        self.tag = TagMode::Custom(tag.to_string());
         self
     }
 
-    /// Adds a directive to the filter for all modules.
+    /// Use the target string as tag
     ///
     /// # Examples
     ///
-    /// Only include messages for warning and above.
-    ///
     /// ```
-    /// # use log::LevelFilter;
     /// # use android_logd_logger::Builder;
     ///
     /// let mut builder = Builder::new();
-    /// builder.filter_level(LevelFilter::Info).init();
+    /// builder.tag_target().init();
     /// ```
-    pub fn filter_level(&mut self, level: LevelFilter) -> &mut Self {
-        self.filter.filter_level(level);
+    pub fn tag_target(&mut self) -> &mut Self {
+        self.tag = TagMode::Target;
         self
     }
 
-    /// Adds filters to the logger.
-    ///
-    /// The given module (if any) will log at most the specified level provided.
-    /// If no module is provided then the filter will apply to all log messages.
+    /// Use the target string as tag and strip off ::.*
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.tag_target_strip().init();
+    /// ```
+    pub fn tag_target_strip(&mut self) -> &mut Self {
+        self.tag = TagMode::TargetStrip;
+        self
+    }
+
+    /// Use the process name from `/proc/self/cmdline` as tag
+    ///
+    /// The first component of the command line is the package or process
+    /// name on Android. If the command line cannot be read, the root module of
+    /// the target is used as tag like with [`tag_target_strip`](Builder::tag_target_strip).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.tag_from_cmdline().init();
+    /// ```
+    pub fn tag_from_cmdline(&mut self) -> &mut Self {
+        self.tag = std::fs::read("/proc/self/cmdline")
+            .ok()
+            .and_then(|cmdline| cmdline_name(&cmdline))
+            .map(TagMode::Custom)
+            .unwrap_or(TagMode::TargetStrip);
+        self
+    }
+
+    /// Prepend module to log message.
+    ///
+    /// If set true the Rust module path is prepended to the log message.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.prepend_module(true).init();
+    /// ```
+    pub fn prepend_module(&mut self, prepend_module: bool) -> &mut Self {
+        self.prepend_module = prepend_module;
+        self
+    }
+
+    /// Sets a callback that renders the message of a record.
+    ///
+    /// The callback is invoked for records that pass the filter and replaces
+    /// the message composition: [`Builder::prepend_module`] is ignored if a
+    /// callback is set. The further processing of the message, e.g.
+    /// [`Builder::strip_ansi`], applies to the rendered message.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder
+    ///     .format(|record| format!("[{}] {}", record.line().unwrap_or(0), record.args()))
+    ///     .init();
+    /// ```
+    pub fn format<F>(&mut self, format: F) -> &mut Self
+    where
+        F: Fn(&log::Record) -> String + Send + Sync + 'static,
+    {
+        self.format = Some(Arc::new(format));
+        self
+    }
+
+    /// Adds a directive to the filter for a specific module.
+    ///
+    /// # Examples
+    ///
+    /// Only include messages for warning and above for logs in `path::to::module`:
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.filter_module("path::to::module", LevelFilter::Info).init();
+    /// ```
+    pub fn filter_module(&mut self, module: &str, level: LevelFilter) -> &mut Self {
+        self.filter.insert(Some(module), level);
+        self
+    }
+
+    /// Adds a directive to the filter for all modules.
+    ///
+    /// # Examples
+    ///
+    /// Only include messages for warning and above.
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.filter_level(LevelFilter::Info).init();
+    /// ```
+    pub fn filter_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.filter.insert(None, level);
+        self
+    }
+
+    /// Adds filters to the logger.
+    ///
+    /// The given module (if any) will log at most the specified level provided.
+    /// If no module is provided then the filter will apply to all log messages.
     ///
     /// # Examples
     ///
     /// Only include messages for warning and above for logs in `path::to::module`:
     ///
     /// ```
-    /// # use log::LevelFilter;
+    /// # use log::LevelFilter;
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.filter(Some("path::to::module"), LevelFilter::Info).init();
+    /// ```
+    pub fn filter(&mut self, module: Option<&str>, level: LevelFilter) -> &mut Self {
+        self.filter.insert(module, level);
+        self
+    }
+
+    /// Parses the directives string in the same form as the `RUST_LOG`
+    /// environment variable.
+    ///
+    /// See the module documentation for more details.
+    pub fn parse_filters(&mut self, filters: &str) -> &mut Self {
+        self.filter.parse(filters);
+        self
+    }
+
+    /// Parses the directives of the environment variable `var` like
+    /// [`parse_filters`](Builder::parse_filters).
+    ///
+    /// Nothing is changed if `var` is not set or not unicode. Directives are
+    /// applied in call order: a later directive for the same module, e.g. of
+    /// [`filter_level`](Builder::filter_level), replaces an earlier one.
+    /// Parse the environment last to let it override the defaults.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.filter_level(LevelFilter::Info).parse_env("MY_APP_LOG").init();
+    /// ```
+    pub fn parse_env(&mut self, var: &str) -> &mut Self {
+        if let Ok(filters) = std::env::var(var) {
+            self.parse_filters(&filters);
+        }
+        self
+    }
+
+    /// Parses the directives of the environment variable `RUST_LOG`. See
+    /// [`parse_env`](Builder::parse_env).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.parse_default_env().init();
+    /// ```
+    pub fn parse_default_env(&mut self) -> &mut Self {
+        self.parse_env("RUST_LOG")
+    }
+
+    /// Enables or disables logging to the pstore filesystem.
+    ///
+    /// Messages logged to the pstore filesystem survive a reboot but not a
+    /// power cycle. By default, logging to the pstore is enabled.
+    #[cfg(target_os = "android")]
+    pub fn pstore(&mut self, log_to_pstore: bool) -> &mut Self {
+        self.pstore = log_to_pstore;
+        self
+    }
+
+    /// Sets the minimum level of records written to the pstore.
+    ///
+    /// The level applies in addition to the filter of the logger. This keeps
+    /// verbose logging in logd while only important records survive a reboot.
+    /// Defaults to [`LevelFilter::Trace`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    /// # use log::LevelFilter;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.pstore_min_level(LevelFilter::Warn).init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn pstore_min_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.pstore_min_level = level;
+        self
+    }
+
+    /// Periodically write a logger health event to [`Buffer::Stats`].
+    ///
+    /// Every `interval` an event with the tag `tag` is written. The value is a
+    /// list of three longs: the number of sent, dropped and reconnect attempts
+    /// since the last emission. The emitting thread is started on
+    /// initialization and can be stopped with [`Logger::stop_stats_event`].
+    ///
+    /// Only the logd socket on Android maintains the sent and reconnect
+    /// counters. On other platforms they always read zero and the dropped
+    /// counter only includes records dropped in the error handler.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    /// # use std::time::Duration;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.stats_event(1000, Duration::from_secs(60)).init();
+    /// ```
+    pub fn stats_event(&mut self, tag: EventTag, interval: Duration) -> &mut Self {
+        self.stats_event = Some((tag, interval));
+        self
+    }
+
+    /// Enables or disables the dry run mode.
+    ///
+    /// In dry run mode log records and events are fully encoded and validated
+    /// (including the size limits) but not written to the logd socket or the
+    /// pmsg device. Validation errors are returned from [`log`](crate::log) and
+    /// the `write_event` functions and passed to the
+    /// [error handler](Builder::on_error) for records of the `log` crate. On non
+    /// Android platforms records are additionally encoded and validated for
+    /// logd and the pstore before being printed. By default, the dry run mode
+    /// is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.dry_run(true).init();
+    /// ```
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Enables or disables compression of messages written to the pstore.
+    ///
+    /// Each message chunk written to the pmsg device is compressed with
+    /// deflate if this reduces its size. Compressed chunks are prefixed with
+    /// [`COMPRESSED_MARKER`] and must be restored with [`decompress`] when
+    /// reading the pstore. **This changes the on-store format**: tools that are
+    /// not aware of the compression (e.g. `logcat -L`) show garbage for
+    /// compressed messages. By default, compression is disabled.
+    ///
+    /// Requires the `pmsg-compress` feature.
+    #[cfg(all(target_os = "android", feature = "pmsg-compress"))]
+    pub fn pmsg_compress(&mut self, compress: bool) -> &mut Self {
+        self.pmsg_compress = compress;
+        self
+    }
+
+    /// Mirrors all records as instant events to the system trace.
+    ///
+    /// Each record is additionally written as atrace instant event with the
+    /// tag and message to the kernel trace marker. This shows the records
+    /// in systrace and perfetto timelines. If the trace marker cannot be
+    /// written, mirroring is disabled and the error is reported once via
+    /// [`on_error`](Builder::on_error). By default, mirroring is disabled.
+    ///
+    /// Requires the `atrace` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.atrace(true).init();
+    /// ```
+    #[cfg(all(target_os = "android", feature = "atrace"))]
+    pub fn atrace(&mut self, atrace: bool) -> &mut Self {
+        self.atrace = atrace;
+        self
+    }
+
+    /// Selects the clock used for the timestamps of records written to the pstore.
+    ///
+    /// The clock of the records sent to logd is not affected. Boot relative
+    /// timestamps are often more meaningful when analysing the pstore after a
+    /// reboot. Defaults to [`ClockSource::Realtime`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, ClockSource};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.pmsg_clock(ClockSource::Boottime).init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn pmsg_clock(&mut self, clock: ClockSource) -> &mut Self {
+        self.pmsg_clock = clock;
+        self
+    }
+
+    /// Writes records and events of `buffer` to the socket at `path`.
+    ///
+    /// Some platforms expose separate sockets for certain buffers. All
+    /// buffers default to `/dev/socket/logdw`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Buffer, Builder};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.buffer_socket(Buffer::Security, "/dev/socket/logdw_security").init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn buffer_socket<P: AsRef<std::path::Path>>(&mut self, buffer: Buffer, path: P) -> &mut Self {
+        self.buffer_sockets.push((buffer, path.as_ref().to_owned()));
+        self
+    }
+
+    /// Sets the minimum interval between attempts to reconnect to logd.
+    ///
+    /// If logd is unavailable, every failed write attempts a reconnect. With
+    /// an interval set, only one thread attempts to reconnect within the
+    /// interval while writes of other threads fail immediately. Defaults to
+    /// zero which attempts a reconnect on every failed write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    /// # use std::time::Duration;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.reconnect_interval(Duration::from_millis(100)).init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn reconnect_interval(&mut self, interval: Duration) -> &mut Self {
+        self.reconnect_interval = interval;
+        self
+    }
+
+    /// Requires credentials to be passed on the logd socket.
+    ///
+    /// Logd attributes an entry to the pid, uid and gid of the
+    /// `SCM_CREDENTIALS` message of the datagram. The kernel attaches these
+    /// credentials only if `SO_PASSCRED` is set on one end of the connection.
+    /// Logd enables it on `/dev/socket/logdw` with the `passcred` option of
+    /// its init service definition. Sockets of this crate set `SO_PASSCRED`
+    /// on their side as well, so credentials are delivered also to sockets
+    /// configured with [`Builder::buffer_socket`] whose owner does not enable
+    /// it.
+    ///
+    /// If enabled, records are not sent on a socket where `SO_PASSCRED` could
+    /// not be set. [`Error::Passcred`] is returned instead. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.require_passcred(true).init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn require_passcred(&mut self, require: bool) -> &mut Self {
+        self.require_passcred = require;
+        self
+    }
+
+    /// Sets the behaviour of writes to logd if the socket is busy.
+    ///
+    /// With [`WriteMode::Discard`] the socket is non blocking and records
+    /// and events are discarded if logd cannot keep up. With
+    /// [`WriteMode::Blocking`] writes wait for logd up to the timeout. Writes
+    /// that time out are reported and counted as dropped. Defaults to
+    /// [`WriteMode::Discard`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, WriteMode};
+    /// # use std::time::Duration;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.write_mode(WriteMode::Blocking { timeout: Duration::from_millis(100) }).init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn write_mode(&mut self, mode: WriteMode) -> &mut Self {
+        self.write_mode = mode;
+        self
+    }
+
+    /// Sets the writer of formatted records.
+    ///
+    /// On non Android platforms records are written to `writer` instead of
+    /// stderr. On Android records are additionally mirrored to `writer`.
+    /// Records are written as lines in the format printed to stderr. The
+    /// writer is shared by all loggers and replaced on each initialization
+    /// with a writer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let file = tempfile::tempfile().unwrap();
+    /// let mut builder = Builder::new();
+    /// builder.target_writer(Box::new(file)).init();
+    /// ```
+    pub fn target_writer(&mut self, writer: Box<dyn io::Write + Send>) -> &mut Self {
+        self.target_writer = Some(writer);
+        self
+    }
+
+    /// Enables or disables printing records to stderr if logd is unavailable.
+    ///
+    /// If a record cannot be sent to logd and no [`Builder::on_error`]
+    /// callback is set, it is printed to stderr in the format used on non
+    /// Android platforms instead of the error message. The log call does not
+    /// fail. This is useful in early boot or recovery contexts. By default,
+    /// the fallback is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.stderr_fallback(true).init();
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn stderr_fallback(&mut self, stderr_fallback: bool) -> &mut Self {
+        self.stderr_fallback = stderr_fallback;
+        self
+    }
+
+    /// Pads or truncates tags to `width` characters in records printed to stderr.
+    ///
+    /// Aligns the messages of records printed on non Android platforms and of
+    /// the stderr fallback on Android. Records written to logd and pmsg are
+    /// not affected. Defaults to zero which prints the tags as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.tag_width(16).init();
+    /// ```
+    pub fn tag_width(&mut self, width: usize) -> &mut Self {
+        self.tag_width = width;
+        self
+    }
+
+    /// Colors the priority in records printed to stderr.
+    ///
+    /// The priority is wrapped in ANSI color codes. With [`ColorMode::Auto`]
+    /// records are colored if stderr is a terminal. Records written to the
+    /// [`target_writer`](Builder::target_writer) are only colored with
+    /// [`ColorMode::Always`]. Defaults to [`ColorMode::Never`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, ColorMode};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.color(ColorMode::Auto).init();
+    /// ```
+    pub fn color(&mut self, color: ColorMode) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the format of timestamps in records printed to stderr.
+    ///
+    /// The format is a format description of the `time` crate, e.g.
+    /// `[hour]:[minute]:[second]`. Defaults to
+    /// `[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]`.
+    /// The format applies to the records of the logger and can be changed with
+    /// [`Logger::timestamp_format`]. Records written with [`log`](crate::log)
+    /// use the default format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timestamp`] if `format` is not a valid format description.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.timestamp_format("[hour]:[minute]:[second]").unwrap().init();
+    ///
+    /// assert!(Builder::new().timestamp_format("[invalid]").is_err());
+    /// ```
+    pub fn timestamp_format(&mut self, format: &str) -> Result<&mut Self, Error> {
+        self.timestamp_format.format = Some(TimestampFormat::parse(format)?);
+        Ok(self)
+    }
+
+    /// Prints timestamps in records printed to stderr in local time.
+    ///
+    /// The offset of the local time zone is determined once when the logger is
+    /// initialized, because reading the time zone is not thread safe. Later
+    /// changes of the time zone, e.g. for daylight saving time, are not
+    /// applied. Falls back to UTC if the offset cannot be determined. Can be
+    /// changed with [`Logger::local_time`]. Defaults to UTC.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.local_time(true).init();
+    /// ```
+    pub fn local_time(&mut self, local_time: bool) -> &mut Self {
+        self.timestamp_format.local = local_time;
+        self
+    }
+
+    /// Selects the representation of priorities in records printed to stderr.
+    ///
+    /// Applies to records printed on non Android platforms and to the stderr
+    /// fallback on Android. Defaults to [`PriorityDisplayStyle::Letter`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, PriorityDisplayStyle};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.priority_display(PriorityDisplayStyle::Word).init();
+    /// ```
+    pub fn priority_display(&mut self, style: PriorityDisplayStyle) -> &mut Self {
+        self.priority_display = style;
+        self
+    }
+
+    /// Enables or disables parsing a priority prefix of forwarded messages.
+    ///
+    /// Messages passed to [`log`](crate::log) with the priority
+    /// [`Priority::_Unknown`] that start with a `logcat` priority letter and a
+    /// slash, e.g. `E/`, are written with this priority and without the
+    /// prefix. This reconstructs the priority of text sources like the output
+    /// of C programs. Messages without a prefix are not changed. Off by
+    /// default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Buffer, Builder, Priority};
+    /// # use std::time::SystemTime;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.parse_embedded_priority(true).init();
+    ///
+    /// // Written with `Priority::Error` and the message "disk full".
+    /// android_logd_logger::log(SystemTime::now(), Buffer::Main, Priority::_Unknown, 0, 0, "relay", "E/disk full").unwrap();
+    /// ```
+    pub fn parse_embedded_priority(&mut self, parse: bool) -> &mut Self {
+        self.parse_embedded_priority = parse;
+        self
+    }
+
+    /// Sets the priority used for records without a level.
+    ///
+    /// Records passed to [`log`](crate::log) with the priority
+    /// [`Priority::_Unknown`] or [`Priority::_Default`] are written with this
+    /// priority instead. By default, the priority is passed through unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, Priority};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.default_priority(Priority::Info).init();
+    /// ```
+    pub fn default_priority(&mut self, priority: Priority) -> &mut Self {
+        self.default_priority = Some(priority);
+        self
+    }
+
+    /// Enables or disables prepending the process name to every event.
+    ///
+    /// The name of the executable is prepended as a string element to the
+    /// value of every event. Values that are not lists are wrapped into a list
+    /// of the name and the value. This increases the size of each event by
+    /// the length of the name plus five bytes (plus two bytes for wrapped
+    /// values) which reduces the space available for the actual value. By
+    /// default, the process name is not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.event_include_process(true).init();
+    /// ```
+    pub fn event_include_process(&mut self, include: bool) -> &mut Self {
+        self.event_include_process = include;
+        self
+    }
+
+    /// Prepends the correlation id of the current thread to events.
+    ///
+    /// The id set with [`set_correlation_id`] or [`with_correlation_id`] is
+    /// prepended as a string element to events written on the same thread.
+    /// Events without a correlation id are not changed. Like with
+    /// [`event_include_process`](Builder::event_include_process), scalar
+    /// values are wrapped into a list, e.g. `42` becomes `["id", 42]`. If
+    /// both options are enabled, the process name comes first:
+    /// `["name", "id", 42]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.event_correlation(true).init();
+    ///
+    /// android_logd_logger::with_correlation_id("request-42", || {
+    ///     android_logd_logger::write_event_now(1, 42).unwrap();
+    /// });
+    /// ```
+    pub fn event_correlation(&mut self, correlation: bool) -> &mut Self {
+        self.event_correlation = correlation;
+        self
+    }
+
+    /// Enables or disables printing records as JSON on non Android platforms.
+    ///
+    /// Records are printed in the format of `logcat` by default. If enabled,
+    /// each record is printed as one line of JSON with the timestamp, the
+    /// process and thread id, the priority, the tag and the message, e.g.
+    /// `{"timestamp":1700000000.000000000,"pid":1,"tid":2,"priority":"I","tag":"app","message":"hello"}`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.format_json(true).init();
+    ///
+    /// log::info!("hello");
+    /// ```
+    #[cfg(not(target_os = "android"))]
+    pub fn format_json(&mut self, json: bool) -> &mut Self {
+        self.format_json = json;
+        self
+    }
+
+    /// Enables or disables printing events as JSON on non Android platforms.
+    ///
+    /// Events are printed to stdout in their debug representation by
+    /// default. If enabled, each event is printed to stderr as one line of
+    /// JSON with the buffer, the timestamp, the tag and the typed values,
+    /// e.g. `{"buffer":"Events","timestamp":1700000000.000000000,"tag":1,"value":{"list":[{"int":1},{"string":"a"}]}}`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.event_stderr_json(true).init();
+    ///
+    /// android_logd_logger::write_event_now(1, (1, "a")).unwrap();
+    /// ```
+    #[cfg(not(target_os = "android"))]
+    pub fn event_stderr_json(&mut self, json: bool) -> &mut Self {
+        self.event_stderr_json = json;
+        self
+    }
+
+    /// Selects the unit of the fractional part of event timestamps.
+    ///
+    /// The header of events contains the seconds and the fraction of the
+    /// second of the timestamp. Defaults to [`TimestampUnit::Nanos`] like the
+    /// header of log records.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, TimestampUnit};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.event_timestamp_unit(TimestampUnit::Micros).init();
+    /// ```
+    pub fn event_timestamp_unit(&mut self, unit: TimestampUnit) -> &mut Self {
+        self.event_timestamp_unit = unit;
+        self
+    }
+
+    /// Overrides the maximum length of a log entry sent to logd.
+    ///
+    /// The limit applies to the encoded log records and events including
+    /// their headers. It does not affect the payload size of the pmsg
+    /// packets. Defaults to 5 KiB which matches `LOGGER_ENTRY_MAX_LEN` of
+    /// logd.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is not within 1 KiB and 64 KiB - 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.max_entry_len(4 * 1024).init();
+    /// ```
+    pub fn max_entry_len(&mut self, len: usize) -> &mut Self {
+        assert!(
+            LOGGER_ENTRY_MAX_LEN_RANGE.contains(&len),
+            "max entry len {} is not within {:?}",
+            len,
+            LOGGER_ENTRY_MAX_LEN_RANGE
+        );
+        self.max_entry_len = len;
+        self
+    }
+
+    /// Sets the byte order of the multi-byte fields of encoded records and
+    /// events.
+    ///
+    /// Defaults to [`ByteOrder::LittleEndian`] which is the byte order of
+    /// logd. Other byte orders are intended for experiments and consumers
+    /// with a different configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, ByteOrder};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.byte_order(ByteOrder::BigEndian).init();
+    /// ```
+    pub fn byte_order(&mut self, byte_order: ByteOrder) -> &mut Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Sets a callback that is invoked if writing a record fails.
+    ///
+    /// Without a callback failures are printed to stderr. The callback is
+    /// invoked without holding the configuration lock and may reconfigure the
+    /// [`Logger`]. Records and events written from within the callback, e.g.
+    /// by reporting the error with `log::error!`, are dropped and counted as
+    /// dropped in the [`Builder::stats_event`] events instead of recursing
+    /// into the failing write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.on_error(|e| log::error!("failed to log: {}", e)).init();
+    /// ```
+    pub fn on_error<F>(&mut self, on_error: F) -> &mut Self
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Arc::new(on_error));
+        self
+    }
+
+    /// Enables or disables writing a record on [`Logger::shutdown`].
+    ///
+    /// The record "logger shutting down" marks the end of the log of a clean
+    /// shutdown in `logcat` and the pstore. The absence of the record hints
+    /// at a crash. Off by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.log_shutdown(true).init();
+    /// ```
+    pub fn log_shutdown(&mut self, log_shutdown: bool) -> &mut Self {
+        self.log_shutdown = log_shutdown;
+        self
+    }
+
+    /// Limits the time [`Logger::flush`] waits for the flush to complete.
+    ///
+    /// By default the flush blocks until the output is flushed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    /// # use std::time::Duration;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.flush_timeout(Duration::from_millis(500)).init();
+    /// ```
+    pub fn flush_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.flush_timeout = Some(timeout);
+        self
+    }
+
+    /// Enables or disables logging without locking the configuration.
+    ///
+    /// By default each record read locks the configuration of the logger.
+    /// If enabled, each thread logs with a copy of the configuration and
+    /// locks the configuration only to renew the copy after it was changed
+    /// with the [`Logger`] handle. This avoids contention of many threads
+    /// logging concurrently at the cost of a copy per thread and change.
+    /// Records logged during a change still use the previous configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.lockfree_hotpath(true).init();
+    /// ```
+    pub fn lockfree_hotpath(&mut self, lockfree: bool) -> &mut Self {
+        self.lockfree_hotpath = lockfree;
+        self
+    }
+
+    /// Enables or disables caching the thread id on Linux and Android.
+    ///
+    /// The id of a thread does not change during its lifetime. If enabled,
+    /// the id is queried with `gettid` once per thread instead of once per
+    /// record. The cache is cleared in the child process after a `fork`.
+    /// Enabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.cache_thread_id(false).init();
+    /// ```
+    pub fn cache_thread_id(&mut self, cache: bool) -> &mut Self {
+        self.cache_thread_id = cache;
+        self
+    }
+
+    /// Writes records additionally to a collector listening on a Unix stream socket.
+    ///
+    /// Each record is written as the datagram that is sent to logd prefixed
+    /// with its length as `u32` in the configured byte order. In contrast to
+    /// the logd socket the stream preserves the order of records and writes
+    /// block if the collector does not keep up. If the connection fails, the
+    /// collector is reconnected like the logd socket, subject to the
+    /// reconnect interval on Android.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.stream_collector("/dev/socket/collector").init();
+    /// ```
+    #[cfg(not(target_os = "windows"))]
+    pub fn stream_collector<P: AsRef<std::path::Path>>(&mut self, path: P) -> &mut Self {
+        self.stream_collector = Some((path.as_ref().to_owned(), false));
+        self
+    }
+
+    /// Writes records only to a collector listening on a Unix stream socket.
+    ///
+    /// Like [`Builder::stream_collector`] but records are not written to logd
+    /// or stderr. Records that cannot be written to the collector are written
+    /// to logd or stderr instead and the failure is reported like other write
+    /// failures, see [`Builder::on_error`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.stream_collector_only("/dev/socket/collector").init();
+    /// ```
+    #[cfg(not(target_os = "windows"))]
+    pub fn stream_collector_only<P: AsRef<std::path::Path>>(&mut self, path: P) -> &mut Self {
+        self.stream_collector = Some((path.as_ref().to_owned(), true));
+        self
+    }
+
+    /// Limits the number of records per tag to `max_per_interval` per `interval`.
+    ///
+    /// Records exceeding the limit are dropped. The first record of a tag
+    /// after the interval elapsed is preceded by a record with the number of
+    /// records suppressed. Drops not reported yet are written on
+    /// [`flush`](log::Log::flush) in the default buffer. At most 256 tags are
+    /// tracked; when more tags log within an interval, the tag tracked longest
+    /// is forgotten and its unreported drops are lost. The default is
+    /// unlimited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    /// # use std::time::Duration;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.rate_limit(100, Duration::from_secs(1)).init();
+    /// ```
+    pub fn rate_limit(&mut self, max_per_interval: u32, interval: Duration) -> &mut Self {
+        self.rate_limit = Some((max_per_interval, interval));
+        self
+    }
+
+    /// Collapses consecutive identical records.
+    ///
+    /// A record with the same buffer, tag, priority and message as the
+    /// previous record is suppressed. Once a different record is logged or
+    /// the logger is flushed a record "last message repeated N times" is
+    /// written, like syslog does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.collapse_repeats(true).init();
+    /// ```
+    pub fn collapse_repeats(&mut self, collapse: bool) -> &mut Self {
+        self.collapse_repeats = collapse;
+        self
+    }
+
+    /// Retains the last `capacity` records in memory.
+    ///
+    /// The records are written to logd as usual and can additionally be read
+    /// with [`Logger::recent`], e.g. to show recent logs in a debug UI.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let logger = Builder::new().parse_filters("info").ring_buffer(100).init();
+    ///
+    /// log::info!("hello");
+    /// assert_eq!(logger.recent().last().unwrap().message, "hello");
+    /// ```
+    pub fn ring_buffer(&mut self, capacity: usize) -> &mut Self {
+        self.ring_buffer = Some(capacity);
+        self
+    }
+
+    /// Limits the number of chunks a message is split into.
+    ///
+    /// Messages exceeding the size of a single entry are split into chunks
+    /// written as separate logd datagrams and pstore packets. The limit
+    /// applies to both. If a message requires more than `max` chunks, the
+    /// last chunk is replaced by a `[truncated, N more chunks dropped]`
+    /// marker. The pstore is additionally limited by the sequence numbers of
+    /// pmsg. Defaults to 256.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.max_chunks(16).init();
+    /// ```
+    pub fn max_chunks(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0, "max chunks must not be zero");
+        self.max_chunks = max;
+        self
+    }
+
+    /// Writes logger internal failures as events to `buffer`.
+    ///
+    /// Failed writes to logd and the pstore and dropped records are reported
+    /// as events with the tag [`SELF_DIAGNOSTICS_EVENT_TAG`]. At most one
+    /// event is written per second. The event contains the number of failures
+    /// that were not reported since the previous event. Failures are still
+    /// printed to stderr.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Buffer, Builder};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.self_diagnostics_buffer(Buffer::Events).init();
+    /// ```
+    pub fn self_diagnostics_buffer(&mut self, buffer: Buffer) -> &mut Self {
+        self.self_diagnostics_buffer = Some(buffer);
+        self
+    }
+
+    /// Allows or suppresses records targeting this crate.
+    ///
+    /// Records with a target of `android_logd_logger` or one of its modules
+    /// are dropped by default to prevent feedback loops.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.allow_self_logs(true).init();
+    /// ```
+    pub fn allow_self_logs(&mut self, allow: bool) -> &mut Self {
+        self.allow_self_logs = allow;
+        self
+    }
+
+    /// Prefixes messages with the priority letter and tag.
+    ///
+    /// Messages are written in the `brief` format of `logcat`, e.g.
+    /// `I/MyTag: message`. This is useful for tools that only read the message
+    /// field. Off by default because `logcat` formats the priority and tag on
+    /// its own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.embed_priority_in_message(true).init();
+    /// ```
+    pub fn embed_priority_in_message(&mut self, embed: bool) -> &mut Self {
+        self.embed_priority = embed;
+        self
+    }
+
+    /// Removes ANSI escape sequences from messages.
+    ///
+    /// Messages relayed from tools that assume a terminal often contain
+    /// color codes which are not rendered by `logcat`. Off by default to
+    /// preserve the messages unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.strip_ansi(true).init();
+    /// ```
+    pub fn strip_ansi(&mut self, strip: bool) -> &mut Self {
+        self.strip_ansi = strip;
+        self
+    }
+
+    /// Removes a leading UTF-8 byte order mark from messages.
+    ///
+    /// Strings forwarded from Windows tools may start with a byte order mark
+    /// that `logcat` shows as stray characters. Applies to the records
+    /// written to logd and pmsg. Off by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.strip_bom(true).init();
+    /// ```
+    pub fn strip_bom(&mut self, strip: bool) -> &mut Self {
+        self.strip_bom = strip;
+        self
+    }
+
+    /// Adds the correlation id of the logging thread to the message of records.
+    ///
+    /// The correlation id set with [`set_correlation_id`] is rendered as
+    /// context field `correlation_id` in the given format. Records of
+    /// threads without a correlation id are not changed. By default the
+    /// context is not added to records.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, ContextFormat};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.context_format(ContextFormat::Prefixed).init();
+    ///
+    /// android_logd_logger::with_correlation_id("request-42", || {
+    ///     // Logged as "correlation_id=request-42 handled"
+    ///     log::info!("handled");
+    /// });
+    /// ```
+    pub fn context_format(&mut self, format: ContextFormat) -> &mut Self {
+        self.context_format = Some(format);
+        self
+    }
+
+    /// Appends a build version to every record.
+    ///
+    /// The version is appended to the message unless a different position is
+    /// selected with [`version_position`](Builder::version_position). Pass a
+    /// short build id, e.g. set via `env!` at build time.
+    ///
+    /// # Examples
+    ///
+    /// ```
     /// # use android_logd_logger::Builder;
     ///
     /// let mut builder = Builder::new();
-    /// builder.filter(Some("path::to::module"), LevelFilter::Info).init();
+    /// builder.version_tag(env!("CARGO_PKG_VERSION")).init();
     /// ```
-    pub fn filter(&mut self, module: Option<&str>, level: LevelFilter) -> &mut Self {
-        self.filter.filter(module, level);
+    pub fn version_tag(&mut self, version: &'static str) -> &mut Self {
+        self.version = Some(version);
         self
     }
 
-    /// Parses the directives string in the same form as the `RUST_LOG`
-    /// environment variable.
+    /// Selects where the version set with [`version_tag`](Builder::version_tag)
+    /// is appended. Defaults to [`VersionPosition::Message`].
     ///
-    /// See the module documentation for more details.
-    pub fn parse_filters(&mut self, filters: &str) -> &mut Self {
-        self.filter.parse(filters);
+    /// If appended to the tag, the tag is shortened so that the tag including
+    /// the version does not exceed 23 bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Builder, VersionPosition};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder
+    ///     .version_tag(env!("CARGO_PKG_VERSION"))
+    ///     .version_position(VersionPosition::Tag)
+    ///     .init();
+    /// ```
+    pub fn version_position(&mut self, position: VersionPosition) -> &mut Self {
+        self.version_position = position;
         self
     }
 
-    /// Enables or disables logging to the pstore filesystem.
+    /// Writes the key-value pairs of records as an event with the tag `tag` to
+    /// [`Buffer::Events`].
     ///
-    /// Messages logged to the pstore filesystem survive a reboot but not a
-    /// power cycle. By default, logging to the pstore is enabled.
-    #[cfg(target_os = "android")]
-    pub fn pstore(&mut self, log_to_pstore: bool) -> &mut Self {
-        self.pstore = log_to_pstore;
+    /// The event value is a list of alternating key and value strings. The
+    /// event is written in addition to the text record of records carrying
+    /// at least one key-value pair.
+    ///
+    /// Requires the `kv` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.kv_as_event(1000).init();
+    ///
+    /// log::info!(user = "alice", attempts = 3; "login failed");
+    /// ```
+    #[cfg(feature = "kv")]
+    pub fn kv_as_event(&mut self, tag: EventTag) -> &mut Self {
+        self.kv_event = Some(tag);
+        self
+    }
+
+    /// Appends the key-value pairs of records to the message.
+    ///
+    /// The pairs are appended as `message [key1=val1 key2=val2]`. Values
+    /// with spaces, brackets, quotes or backslashes are quoted and escaped.
+    /// Records without key-value pairs are not changed. By default, the
+    /// key-value pairs are not rendered.
+    ///
+    /// Requires the `kv` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.render_kv(true).init();
+    ///
+    /// log::info!(user = "alice", attempts = 3; "login failed");
+    /// ```
+    #[cfg(feature = "kv")]
+    pub fn render_kv(&mut self, render: bool) -> &mut Self {
+        self.render_kv = render;
+        self
+    }
+
+    /// Uses the `_ts` key-value pair of records as timestamp.
+    ///
+    /// The value is the time since the Unix epoch in nanoseconds as integer
+    /// or string. Records without or with an invalid `_ts` field are logged
+    /// with the current time. This allows to replay historical records with
+    /// their original timestamps through the `log` macros.
+    ///
+    /// Requires the `kv` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    /// builder.honor_ts_field(true).init();
+    ///
+    /// log::error!(_ts = 1_600_000_000_000_000_000u64; "replayed");
+    /// ```
+    #[cfg(feature = "kv")]
+    pub fn honor_ts_field(&mut self, honor: bool) -> &mut Self {
+        self.honor_ts_field = honor;
         self
     }
 
+    /// Builds the logger configuration.
+    pub(crate) fn configuration(&self) -> Configuration {
+        Configuration {
+            filter: self.filter.build(),
+            directives: self.filter.clone(),
+            tag: self.tag.clone(),
+            prepend_module: self.prepend_module,
+            format: self.format.clone(),
+            timestamp_format: self.timestamp_format.clone(),
+            pstore: self.pstore,
+            buffer_id: self.buffer.unwrap_or(Buffer::Main),
+            level_routes: self.level_routes.clone(),
+            mirror_buffers: self.mirror_buffers.clone(),
+            #[cfg(feature = "pmsg-compress")]
+            pmsg_compress: self.pmsg_compress,
+            #[cfg(feature = "atrace")]
+            atrace: self.atrace,
+            pmsg_clock: self.pmsg_clock,
+            pstore_min_level: self.pstore_min_level,
+            stats_emitter: None,
+            allow_self_logs: self.allow_self_logs,
+            embed_priority: self.embed_priority,
+            strip_ansi: self.strip_ansi,
+            strip_bom: self.strip_bom,
+            context_format: self.context_format,
+            log_shutdown: self.log_shutdown,
+            version: self.version,
+            version_position: self.version_position,
+            ring_buffer: self
+                .ring_buffer
+                .map(|capacity| Arc::new(ring_buffer::RingBuffer::new(capacity))),
+            rate_limiter: self
+                .rate_limit
+                .map(|(max, interval)| Arc::new(rate_limit::RateLimiter::new(max, interval))),
+            repeats: self.collapse_repeats.then(Default::default),
+            on_error: self.on_error.clone(),
+            flush_timeout: self.flush_timeout,
+            #[cfg(feature = "kv")]
+            kv_event: self.kv_event,
+            #[cfg(feature = "kv")]
+            render_kv: self.render_kv,
+            #[cfg(feature = "kv")]
+            honor_ts_field: self.honor_ts_field,
+        }
+    }
+
     /// Initializes the global logger with the built logd logger.
     ///
     /// This should be called early in the execution of a Rust program. Any log
-    /// events that occur before initialization will be ignored.
+    /// events that occur before initialization will be ignored unless
+    /// [`capture_preinit`] was called.
     ///
     /// # Errors
     ///
     /// This function will fail if it is called more than once, or if another
     /// library has already initialized a global logger.
     pub fn try_init(&mut self) -> Result<Logger, SetLoggerError> {
-        let configuration = Configuration {
-            filter: self.filter.build(),
-            tag: self.tag.clone(),
-            prepend_module: self.prepend_module,
-            pstore: self.pstore,
-            buffer_id: self.buffer.unwrap_or(Buffer::Main),
-        };
+        let configuration = self.configuration();
         let max_level = configuration.filter.filter();
         let configuration = Arc::new(RwLock::new(configuration));
 
         let logger = Logger {
             configuration: configuration.clone(),
+            pending_level: Default::default(),
+        };
+        let logger_impl = logger::LoggerImpl::new(configuration, self.lockfree_hotpath).expect("failed to build logger");
+
+        // Take over the global logger installed by `capture_preinit`.
+        let mut preinit = None;
+        let result = if preinit::claim() {
+            preinit = Some(logger_impl);
+            Ok(())
+        } else {
+            set_boxed_logger(Box::new(logger_impl))
         };
-        let logger_impl = logger::LoggerImpl::new(configuration).expect("failed to build logger");
 
-        set_boxed_logger(Box::new(logger_impl))
+        result
             .map(|_| {
                 log::set_max_level(max_level);
+                DRY_RUN.store(self.dry_run, Ordering::Relaxed);
+                STDERR_FALLBACK.store(self.stderr_fallback, Ordering::Relaxed);
+                if let Some(writer) = self.target_writer.take() {
+                    *TARGET_WRITER.lock() = Some(writer);
+                }
+                TAG_WIDTH.store(self.tag_width, Ordering::Relaxed);
+                *COLOR.write() = self.color;
+                thread::CACHE_ID.store(self.cache_thread_id, Ordering::Relaxed);
+                *PRIORITY_DISPLAY.write() = self.priority_display;
+                *DEFAULT_PRIORITY.write() = self.default_priority;
+                EVENT_INCLUDE_PROCESS.store(self.event_include_process, Ordering::Relaxed);
+                PARSE_EMBEDDED_PRIORITY.store(self.parse_embedded_priority, Ordering::Relaxed);
+                EVENT_CORRELATION.store(self.event_correlation, Ordering::Relaxed);
+                MAX_ENTRY_LEN.store(self.max_entry_len, Ordering::Relaxed);
+                MAX_CHUNKS.store(self.max_chunks, Ordering::Relaxed);
+                *BYTE_ORDER.write() = self.byte_order;
+                *EVENT_TIMESTAMP_UNIT.write() = self.event_timestamp_unit;
+                #[cfg(not(target_os = "android"))]
+                EVENT_STDERR_JSON.store(self.event_stderr_json, Ordering::Relaxed);
+                #[cfg(not(target_os = "android"))]
+                FORMAT_JSON.store(self.format_json, Ordering::Relaxed);
+                *RECONNECT_INTERVAL.write() = self.reconnect_interval;
+                REQUIRE_PASSCRED.store(self.require_passcred, Ordering::Relaxed);
+                *WRITE_MODE.write() = self.write_mode;
+                *LOCAL_OFFSET.write() = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
+                #[cfg(target_os = "android")]
+                logd::set_write_mode(self.write_mode);
+                *diagnostics::BUFFER.write() = self.self_diagnostics_buffer;
+                for (name, id) in &self.custom_buffers {
+                    CustomBuffers::register(name, *id);
+                }
+                #[cfg(not(target_os = "windows"))]
+                if let Some((path, exclusive)) = &self.stream_collector {
+                    stream::set_collector(path);
+                    stream::EXCLUSIVE.store(*exclusive, Ordering::Relaxed);
+                }
+                #[cfg(target_os = "android")]
+                for (buffer, path) in &self.buffer_sockets {
+                    logd::set_buffer_socket(*buffer, path);
+                }
+                if let Some(logger_impl) = preinit {
+                    preinit::forward(Box::new(logger_impl));
+                }
+            })
+            .map(|_| {
+                if let Some((tag, interval)) = self.stats_event {
+                    let emitter = stats::StatsEmitter::spawn(tag, interval).expect("failed to spawn stats thread");
+                    logger.write().stats_emitter = Some(emitter);
+                }
+                logger
             })
-            .map(|_| logger)
     }
 
     /// Initializes the global logger with the built logger.
     ///
     /// This should be called early in the execution of a Rust program. Any log
-    /// events that occur before initialization will be ignored.
+    /// events that occur before initialization will be ignored unless
+    /// [`capture_preinit`] was called.
     ///
     /// # Panics
     ///
@@ -428,6 +2509,103 @@ impl Builder {
         self.try_init()
             .expect("Builder::init should not be called after logger initialized")
     }
+
+    /// Initializes the global logger with the built logger unless a global
+    /// logger is already set.
+    ///
+    /// Returns `None` if a global logger, including one not built by this
+    /// crate, is already set. This allows libraries to initialize logging
+    /// defensively.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Builder;
+    ///
+    /// assert!(Builder::new().init_once().is_some());
+    /// assert!(Builder::new().init_once().is_none());
+    /// ```
+    pub fn init_once(&mut self) -> Option<Logger> {
+        self.try_init().ok()
+    }
+}
+
+/// Encode a log entry into the datagrams that are sent to the logd writer socket
+///
+/// No data is sent. This can be used to inspect or forward the exact frames
+/// that [`log`] writes to logd. Messages exceeding the maximum entry length
+/// are split into multiple datagrams.
+///
+/// # Example
+///
+/// ```
+/// # use android_logd_logger::{Buffer, Priority};
+/// # use std::time::SystemTime;
+///
+/// let datagrams = android_logd_logger::logd_datagrams(SystemTime::now(), Buffer::Main, Priority::Info, 0, 0, "tag", "message");
+/// assert_eq!(datagrams.count(), 1);
+/// ```
+#[cfg(not(target_os = "windows"))]
+pub fn logd_datagrams<'a>(
+    timestamp: SystemTime,
+    buffer_id: Buffer,
+    priority: Priority,
+    pid: u32,
+    thread_id: u16,
+    tag: &'a str,
+    message: &'a str,
+) -> impl Iterator<Item = bytes::Bytes> + 'a {
+    let record = Record {
+        timestamp,
+        pid,
+        thread_id,
+        buffer_id,
+        tag,
+        priority,
+        message,
+        uid: None,
+    };
+
+    logd::datagrams(&record)
+}
+
+/// Encode a log entry into the packets that are written to the pmsg device
+///
+/// No data is written. This can be used to inspect the exact packets that
+/// are written to the pstore, e.g. to reconstruct pstore contents. The
+/// packets are not compressed.
+///
+/// # Example
+///
+/// ```
+/// # use android_logd_logger::{Buffer, Priority};
+/// # use std::time::SystemTime;
+///
+/// let packets = android_logd_logger::pmsg_packets(SystemTime::now(), Buffer::Main, Priority::Info, 0, 0, "tag", "message");
+/// assert_eq!(packets.len(), 1);
+/// ```
+pub fn pmsg_packets(
+    timestamp: SystemTime,
+    buffer_id: Buffer,
+    priority: Priority,
+    pid: u32,
+    thread_id: u16,
+    tag: &str,
+    message: &str,
+) -> Vec<bytes::Bytes> {
+    let record = Record {
+        timestamp,
+        pid,
+        thread_id,
+        buffer_id,
+        tag,
+        priority,
+        message,
+        uid: None,
+    };
+    let timestamp = timestamp.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+
+    pmsg::packets(&record, timestamp, false)
 }
 
 /// Construct a log entry and send it to the logd writer socket
@@ -447,11 +2625,22 @@ pub fn log(
     timestamp: SystemTime,
     buffer_id: Buffer,
     priority: Priority,
-    pid: u16,
+    pid: u32,
     thread_id: u16,
     tag: &str,
     message: &str,
 ) -> Result<(), Error> {
+    if !writable() {
+        return Ok(());
+    }
+
+    let (priority, message) = match priority {
+        Priority::_Unknown if PARSE_EMBEDDED_PRIORITY.load(Ordering::Relaxed) => {
+            embedded_priority(message).unwrap_or((priority, message))
+        }
+        _ => (priority, message),
+    };
+    let priority = default_priority(priority);
     let record = Record {
         timestamp,
         pid,
@@ -460,11 +2649,16 @@ pub fn log(
         tag,
         priority,
         message,
+        uid: None,
     };
 
-    logd::log(&record);
-
-    Ok(())
+    match logd::log(&record) {
+        Err(e) if STDERR_FALLBACK.load(Ordering::Relaxed) => {
+            print_failed(&record, &TimestampFormat::default(), &e);
+            Ok(())
+        }
+        result => result,
+    }
 }
 
 /// Construct a log entry
@@ -484,11 +2678,22 @@ pub fn log(
     timestamp: SystemTime,
     buffer_id: Buffer,
     priority: Priority,
-    pid: u16,
+    pid: u32,
     thread_id: u16,
     tag: &str,
     message: &str,
 ) -> Result<(), Error> {
+    if !writable() {
+        return Ok(());
+    }
+
+    let (priority, message) = match priority {
+        Priority::_Unknown if PARSE_EMBEDDED_PRIORITY.load(Ordering::Relaxed) => {
+            embedded_priority(message).unwrap_or((priority, message))
+        }
+        _ => (priority, message),
+    };
+    let priority = default_priority(priority);
     let record = Record {
         timestamp,
         pid,
@@ -497,24 +2702,118 @@ pub fn log(
         tag,
         priority,
         message,
+        uid: None,
     };
 
-    log_record(&record)
+    log_record(&record, &TimestampFormat::default())
+}
+
+/// Implementation of the [`logd_log!`] macro.
+#[doc(hidden)]
+pub fn __private_log(buffer_id: Buffer, priority: impl Into<Priority>, module_path: &str, args: fmt::Arguments) {
+    if !writable() {
+        return;
+    }
+
+    let tag = module_path.split_once("::").map(|(tag, _)| tag).unwrap_or(module_path);
+    let message = args.to_string();
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: std::process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id,
+        tag,
+        priority: priority.into(),
+        message: &message,
+        uid: None,
+    };
+
+    if let Err(e) = log_record(&record, &TimestampFormat::default()) {
+        print_failed(&record, &TimestampFormat::default(), &e);
+    }
+}
+
+/// Print a record that could not be written to stderr. With the
+/// [stderr fallback](Builder::stderr_fallback) the record is printed instead
+/// of the error.
+fn print_failed(record: &Record, timestamps: &TimestampFormat, error: &Error) {
+    let fallback = STDERR_FALLBACK
+        .load(Ordering::Relaxed)
+        .then(|| format_record(record, stderr_color(), timestamps).ok())
+        .flatten();
+    match fallback {
+        Some(line) => eprintln!("{}", line),
+        None => eprintln!("Failed to log record \"{}: {}\": {}", record.tag, record.message, error),
+    }
 }
 
 #[cfg(target_os = "android")]
-fn log_record(record: &Record) -> Result<(), Error> {
-    logd::log(record);
-    Ok(())
+fn log_record(record: &Record, timestamps: &TimestampFormat) -> Result<(), Error> {
+    let collector = stream::log(record);
+    if let Ok(true) = collector {
+        return Ok(());
+    }
+    let logd = logd::log(record);
+    write_target(record, timestamps)?;
+    collector?;
+    logd
 }
 
 #[cfg(not(target_os = "android"))]
-fn log_record(record: &Record) -> Result<(), Error> {
-    use std::time::UNIX_EPOCH;
+fn log_record(record: &Record, timestamps: &TimestampFormat) -> Result<(), Error> {
+    #[cfg(not(target_os = "windows"))]
+    let collector = stream::log(record);
+    #[cfg(not(target_os = "windows"))]
+    if let Ok(true) = collector {
+        return Ok(());
+    }
 
-    const DATE_TIME_FORMAT: &[time::format_description::FormatItem<'_>] =
-        time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]");
+    #[cfg(not(target_os = "windows"))]
+    if dry_run() {
+        logd::validate_record(record)?;
+    }
+
+    if !write_target(record, timestamps)? {
+        eprintln!("{}", format_line(record, stderr_color(), timestamps)?);
+    }
+    #[cfg(not(target_os = "windows"))]
+    collector?;
+    Ok(())
+}
+
+/// Format a record in the configured output format. The priority is colored
+/// if `color` is set and the format is not JSON.
+fn format_line(record: &Record, color: bool, timestamps: &TimestampFormat) -> Result<String, Error> {
+    #[cfg(not(target_os = "android"))]
+    if FORMAT_JSON.load(Ordering::Relaxed) {
+        return Ok(format_record_json(record));
+    }
+    format_record(record, color, timestamps)
+}
+
+/// Format a record as a JSON object.
+#[cfg(not(target_os = "android"))]
+fn format_record_json(record: &Record) -> String {
+    let timestamp = record.timestamp.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let mut json = format!(
+        "{{\"timestamp\":{}.{:09},\"pid\":{},\"tid\":{},\"priority\":",
+        timestamp.as_secs(),
+        timestamp.subsec_nanos(),
+        record.pid,
+        record.thread_id
+    );
+    events::push_json_str(&mut json, &PRIORITY_DISPLAY.read().format(record.priority));
+    json.push_str(",\"tag\":");
+    events::push_json_str(&mut json, record.tag);
+    json.push_str(",\"message\":");
+    events::push_json_str(&mut json, record.message);
+    json.push('}');
+    json
+}
 
+/// Format a record in the default format of `logcat`. The priority is wrapped
+/// in ANSI color codes if `color` is set.
+fn format_record(record: &Record, color: bool, timestamps: &TimestampFormat) -> Result<String, Error> {
     let Record {
         timestamp,
         tag,
@@ -525,14 +2824,262 @@ fn log_record(record: &Record) -> Result<(), Error> {
         ..
     } = record;
 
-    let timestamp = timestamp
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| Error::Timestamp(e.to_string()))
-        .and_then(|ts| {
-            time::OffsetDateTime::from_unix_timestamp_nanos(ts.as_nanos() as i128).map_err(|e| Error::Timestamp(e.to_string()))
-        })
-        .and_then(|ts| ts.format(&DATE_TIME_FORMAT).map_err(|e| Error::Timestamp(e.to_string())))?;
+    let timestamp = timestamps.format(*timestamp)?;
 
-    eprintln!("{} {} {} {} {}: {}", timestamp, pid, thread_id, priority, tag, message);
-    Ok(())
+    let width = TAG_WIDTH.load(Ordering::Relaxed);
+    let tag = if width == 0 {
+        Cow::Borrowed(*tag)
+    } else {
+        Cow::Owned(format!("{:<width$.width$}", tag, width = width))
+    };
+
+    let priority = if color {
+        format!(
+            "{}{}\x1b[0m",
+            priority_color(*priority),
+            PRIORITY_DISPLAY.read().format(*priority)
+        )
+    } else {
+        PRIORITY_DISPLAY.read().format(*priority)
+    };
+
+    Ok(format!(
+        "{} {} {} {} {}: {}",
+        timestamp, pid, thread_id, priority, tag, message
+    ))
+}
+
+/// ANSI color code of `priority`.
+fn priority_color(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Verbose => "\x1b[90m",
+        Priority::Debug => "\x1b[34m",
+        Priority::Info => "\x1b[32m",
+        Priority::Warn => "\x1b[33m",
+        Priority::Error => "\x1b[31m",
+        Priority::_Fatal => "\x1b[1;31m",
+        Priority::_Unknown | Priority::_Default | Priority::_Silent => "\x1b[0m",
+    }
+}
+
+/// Format `timestamp` with `format` or the default format.
+fn format_date_time(
+    timestamp: time::OffsetDateTime,
+    format: Option<&time::format_description::OwnedFormatItem>,
+) -> Result<String, Error> {
+    const DATE_TIME_FORMAT: &[time::format_description::FormatItem<'_>] =
+        time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]");
+
+    match format {
+        Some(format) => timestamp.format(format),
+        None => timestamp.format(&DATE_TIME_FORMAT),
+    }
+    .map_err(|e| Error::Timestamp(e.to_string()))
+}
+
+#[test]
+fn parse_env() {
+    const VAR: &str = "ANDROID_LOGD_LOGGER_TEST_PARSE_ENV";
+
+    let mut builder = Builder::new();
+    builder.filter_level(LevelFilter::Warn).parse_env(VAR);
+    assert_eq!(builder.configuration().filter.filter(), LevelFilter::Warn);
+
+    std::env::set_var(VAR, "info,foo=trace");
+    builder.parse_env(VAR);
+    std::env::remove_var(VAR);
+    let configuration = builder.configuration();
+    assert_eq!(configuration.filter.filter(), LevelFilter::Trace);
+    assert_eq!(
+        configuration.directives.modules(),
+        vec![("foo".to_string(), LevelFilter::Trace)]
+    );
+
+    builder.filter_level(LevelFilter::Error);
+    let metadata = log::Metadata::builder().target("bar").level(log::Level::Warn).build();
+    assert!(!builder.configuration().filter.enabled(&metadata));
+}
+
+#[test]
+fn priority_names() {
+    use std::str::FromStr;
+
+    for (names, priority) in [
+        (["v", "V", "verbose", "VERBOSE"], Priority::Verbose),
+        (["d", "D", "debug", "Debug"], Priority::Debug),
+        (["i", "I", "info", "INFO"], Priority::Info),
+        (["w", "W", "warn", "wArN"], Priority::Warn),
+        (["e", "E", "error", "Error"], Priority::Error),
+    ] {
+        for name in names {
+            assert_eq!(Priority::from_str(name).unwrap() as u8, priority as u8);
+        }
+        assert_eq!(Priority::from_str(&priority.to_string()).unwrap() as u8, priority as u8);
+    }
+    for name in ["", "U", "F", "S", "fatal", "silent", "warning"] {
+        assert!(matches!(Priority::from_str(name), Err(Error::UnknownPriority(n)) if n == name));
+    }
+}
+
+#[test]
+fn buffer_names() {
+    use std::str::FromStr;
+
+    let buffers = [
+        Buffer::Main,
+        Buffer::Radio,
+        Buffer::Events,
+        Buffer::System,
+        Buffer::Crash,
+        Buffer::Stats,
+        Buffer::Security,
+        Buffer::Custom(0),
+        Buffer::Custom(200),
+    ];
+    for buffer in buffers {
+        assert_eq!(Buffer::from_str(&buffer.to_string()).unwrap(), buffer);
+    }
+    for name in ["", "Main", "256", "-1"] {
+        assert!(matches!(Buffer::from_str(name), Err(Error::UnknownBuffer(n)) if n == name));
+    }
+}
+
+#[test]
+fn colored() {
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: 1,
+        thread_id: 2,
+        buffer_id: Buffer::Main,
+        tag: "tag",
+        priority: Priority::Warn,
+        message: "message",
+        uid: None,
+    };
+    let plain = format_record(&record, false, &TimestampFormat::default()).unwrap();
+    assert!(plain.ends_with(" 1 2 W tag: message"));
+    assert!(!plain.contains('\x1b'));
+
+    let colored = format_record(&record, true, &TimestampFormat::default()).unwrap();
+    assert!(colored.ends_with(" 1 2 \x1b[33mW\x1b[0m tag: message"));
+}
+
+#[test]
+fn timestamp_formatted() {
+    let timestamp = std::time::UNIX_EPOCH + Duration::new(86_400, 5_000_000);
+    let mut timestamps = TimestampFormat::default();
+    assert_eq!(timestamps.format(timestamp).unwrap(), "1970-01-02 00:00:00.005");
+
+    timestamps.format =
+        Some(TimestampFormat::parse("[hour]:[minute] [offset_hour sign:mandatory]:[offset_minute]:[offset_second]").unwrap());
+    assert_eq!(timestamps.format(timestamp).unwrap(), "00:00 +00:00:00");
+
+    let offset = time::UtcOffset::from_hms(2, 30, 0).unwrap();
+    let local = time::OffsetDateTime::from_unix_timestamp(86_400).unwrap().to_offset(offset);
+    assert_eq!(
+        format_date_time(local, timestamps.format.as_deref()).unwrap(),
+        "02:30 +02:30:00"
+    );
+    assert_eq!(format_date_time(local, None).unwrap(), "1970-01-02 02:30:00.000");
+}
+
+#[test]
+#[cfg(not(target_os = "android"))]
+fn target_writer() {
+    struct Capture(Arc<parking_lot::Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let captured = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    *TARGET_WRITER.lock() = Some(Box::new(Capture(captured.clone())));
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: 1,
+        thread_id: 2,
+        buffer_id: Buffer::Main,
+        tag: "target_writer",
+        priority: Priority::Info,
+        message: "captured",
+        uid: None,
+    };
+    let result = log_record(&record, &TimestampFormat::default());
+    *TARGET_WRITER.lock() = None;
+    result.unwrap();
+
+    let captured = String::from_utf8(captured.lock().clone()).unwrap();
+    assert!(captured.contains(" 1 2 I target_writer: captured\n"));
+}
+
+#[test]
+#[cfg(not(target_os = "android"))]
+fn record_json() {
+    let record = Record {
+        timestamp: std::time::UNIX_EPOCH + Duration::new(1, 5),
+        pid: 1,
+        thread_id: 2,
+        buffer_id: Buffer::Main,
+        tag: "tag",
+        priority: Priority::Info,
+        message: "say \"hi\"\n\u{1}",
+        uid: None,
+    };
+    let json = format_record_json(&record);
+    assert_eq!(
+        json,
+        r#"{"timestamp":1.000000005,"pid":1,"tid":2,"priority":"I","tag":"tag","message":"say \"hi\"\n\u0001"}"#
+    );
+
+    #[cfg(feature = "json")]
+    {
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["message"], "say \"hi\"\n\u{1}");
+        assert_eq!(value["tid"], 2);
+    }
+}
+
+#[test]
+fn cmdline() {
+    assert_eq!(cmdline_name(b"com.example.app\0").as_deref(), Some("com.example.app"));
+    assert_eq!(cmdline_name(b"/system/bin/foo\0--bar\0").as_deref(), Some("/system/bin/foo"));
+    assert_eq!(cmdline_name(b"\0"), None);
+    assert_eq!(cmdline_name(b""), None);
+}
+
+#[test]
+fn syslog_severity() {
+    for severity in 0..=7 {
+        let priority = Priority::from_syslog_severity(severity);
+        assert_eq!(
+            Priority::from_syslog_severity(priority.to_syslog_severity()) as u8,
+            priority as u8
+        );
+    }
+}
+
+#[test]
+fn priority_display_style() {
+    assert_eq!(PriorityDisplayStyle::Letter.format(Priority::Debug), "D");
+    assert_eq!(PriorityDisplayStyle::LowerLetter.format(Priority::Warn), "w");
+    assert_eq!(PriorityDisplayStyle::Numeric.format(Priority::_Default), "1");
+    assert_eq!(PriorityDisplayStyle::Word.format(Priority::Info), "INFO");
+}
+
+#[test]
+fn embedded_priority_parsed() {
+    assert!(matches!(
+        embedded_priority("E/disk full"),
+        Some((Priority::Error, "disk full"))
+    ));
+    assert!(matches!(embedded_priority("A/abort"), Some((Priority::_Fatal, "abort"))));
+    assert!(embedded_priority("X/unknown").is_none());
+    assert!(embedded_priority("E").is_none());
+    assert!(embedded_priority("é/").is_none());
 }
@@ -1,26 +1,58 @@
 use std::{
     io::{self, ErrorKind},
-    os::unix::net::UnixDatagram,
-    path::Path,
-    time::UNIX_EPOCH,
+    mem,
+    os::unix::{io::AsRawFd, net::UnixDatagram},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use bytes::BufMut;
+use bytes::{BufMut, Bytes};
 use parking_lot::RwLockUpgradableReadGuard;
 
-use crate::{thread, Buffer, Event, Record, LOGGER_ENTRY_MAX_LEN};
+use crate::{
+    byte_order, diagnostics,
+    logging_iterator::{limit_chunks, NewlineScaledChunkIterator},
+    max_entry_len,
+    stats::Counters,
+    thread, Buffer, Error, Event, EventTag, Record, TimestampUnit, WriteMode,
+};
 
 /// Logd write socket path
 const LOGDW: &str = "/dev/socket/logdw";
 
+/// Maximum time [`write_event_blocking`] waits for logd in [`WriteMode::Discard`].
+pub(crate) const BLOCKING_EVENT_TIMEOUT: Duration = Duration::from_secs(1);
+
 lazy_static::lazy_static! {
+    /// Reference for the timestamps of reconnect attempts.
+    static ref START: Instant = Instant::now();
     static ref SOCKET: LogdSocket = LogdSocket::connect(Path::new(LOGDW));
+    /// Sockets of buffers that are not written via `LOGDW`.
+    static ref BUFFER_SOCKETS: parking_lot::RwLock<Vec<(u8, Arc<LogdSocket>)>> = parking_lot::RwLock::new(Vec::new());
 }
 
 /// Logd write socket abstraction. Sends never fail and on each send a reconnect
 /// attempt is made.
 struct LogdSocket {
+    path: PathBuf,
     socket: parking_lot::RwLock<UnixDatagram>,
+    /// Number of datagrams handed to the socket.
+    sent: AtomicU64,
+    /// Number of datagrams discarded.
+    dropped: AtomicU64,
+    /// Number of reconnect attempts.
+    reconnects: AtomicU64,
+    /// Time of the last reconnect attempt in nanoseconds since `START` plus
+    /// one. Zero if there was no attempt yet.
+    last_reconnect: AtomicU64,
+    /// True if `SO_PASSCRED` is set on the current socket.
+    passcred: AtomicBool,
+    /// True if the socket is in blocking mode. See [`WriteMode`].
+    blocking: AtomicBool,
 }
 
 impl LogdSocket {
@@ -35,81 +67,488 @@ impl LogdSocket {
         // Ignore connect failures because this will be retried.
         socket.connect(path).ok();
 
-        // The logd socket is a datagram socket. If a write fails the logd might be
-        // under heavy load and is unable to process this write.
-        socket
-            .set_nonblocking(true)
-            .expect("failed to set the logd socket to non blocking");
+        let passcred = set_passcred(&socket).is_ok();
+
+        let mode = crate::write_mode();
+        configure(&socket, mode).expect("failed to configure the logd socket write mode");
 
         let lock = parking_lot::RwLock::new(socket);
-        LogdSocket { socket: lock }
+        LogdSocket {
+            path: path.to_owned(),
+            socket: lock,
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            last_reconnect: AtomicU64::new(0),
+            passcred: AtomicBool::new(passcred),
+            blocking: AtomicBool::new(mode != WriteMode::Discard),
+        }
+    }
+
+    /// Apply `mode` to the current socket.
+    pub fn set_write_mode(&self, mode: WriteMode) -> io::Result<()> {
+        configure(&self.socket.read(), mode)?;
+        self.blocking.store(mode != WriteMode::Discard, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns true if `SO_PASSCRED` is set on the current socket.
+    pub fn passcred(&self) -> bool {
+        self.passcred.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of the socket counters.
+    pub fn counters(&self) -> Counters {
+        Counters {
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+        }
     }
 
     /// Write a log entry to the log daemon. If a first write attempt fails, try to
     /// reconnect to the log daemon and try again.
     pub fn send(&self, buffer: &[u8]) -> io::Result<()> {
+        self.send_throttled(buffer, crate::reconnect_interval())
+    }
+
+    /// Write a log entry to the log daemon. Reconnect attempts are limited to
+    /// one within `interval`.
+    ///
+    /// The upgradable lock serializes the writers that may reconnect, so at
+    /// most one reconnect socket exists at a time.
+    fn send_throttled(&self, buffer: &[u8], interval: Duration) -> io::Result<()> {
         let lock = self.socket.upgradable_read();
         match lock.send(buffer) {
-            Ok(_) => (),
-            Err(e) if e.kind() == ErrorKind::WouldBlock => (), // discard
-            Err(_) => {
-                // Try to create an unbounded socket. Expect this to work.
-                let socket = UnixDatagram::unbound()?;
-
-                // Upgrade the read lock and replace the socket if the sent attempt is successful.
-                let mut lock = RwLockUpgradableReadGuard::upgrade(lock);
-                socket.connect(LOGDW)?;
-                socket.set_nonblocking(true)?;
-
-                socket.send(buffer)?;
-
-                // Assign the new socket to the lock. In the worst case one or more threads
-                // are opening sockets to logd which are immediately closed.
-                *lock = socket;
+            Ok(_) => {
+                self.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock && self.blocking.load(Ordering::Relaxed) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(io::Error::new(ErrorKind::TimedOut, "timeout writing to logd"));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                // discard
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                diagnostics::report("drop", format_args!("logd socket is busy"));
+            }
+            Err(e) => {
+                if !self.reconnect_allowed(interval) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Err(e);
+                }
+                self.reconnects.fetch_add(1, Ordering::Relaxed);
+                let result = self.reconnect(lock, buffer);
+                match result {
+                    Ok(_) => self.sent.fetch_add(1, Ordering::Relaxed),
+                    Err(_) => self.dropped.fetch_add(1, Ordering::Relaxed),
+                };
+                result?;
             }
         }
         Ok(())
     }
+
+    /// Write a log entry to the log daemon with the credentials of `uid`.
+    ///
+    /// Passing foreign credentials requires `CAP_SETUID`. If the credentials
+    /// are rejected the entry is sent with the credentials of the process.
+    pub fn send_as(&self, buffer: &[u8], uid: u32) -> io::Result<()> {
+        let blocking = self.blocking.load(Ordering::Relaxed);
+        let result = {
+            let socket = self.socket.read();
+            send_with_credentials(&socket, buffer, uid, blocking)
+        };
+        match result {
+            Ok(()) => {
+                self.sent.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock && blocking => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Err(io::Error::new(ErrorKind::TimedOut, "timeout writing to logd"))
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(_) => self.send(buffer),
+        }
+    }
+
+    /// Write a log entry to the log daemon on a new blocking socket.
+    ///
+    /// The shared socket may be non blocking. The entry is sent on a dedicated
+    /// socket in order to wait up to `timeout` until the kernel accepts the
+    /// datagram. A zero `timeout` blocks without limit.
+    pub fn send_blocking(&self, buffer: &[u8], timeout: Duration) -> Result<(), Error> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(&self.path)?;
+        configure(&socket, WriteMode::Blocking { timeout })?;
+        if set_passcred(&socket).is_err() && crate::require_passcred() {
+            return Err(Error::Passcred);
+        }
+
+        match socket.send(buffer) {
+            Ok(_) => {
+                self.sent.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(e) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                match e.kind() {
+                    ErrorKind::WouldBlock => Err(io::Error::new(ErrorKind::TimedOut, "timeout writing to logd").into()),
+                    _ => Err(e.into()),
+                }
+            }
+        }
+    }
+
+    /// Returns true if a reconnect may be attempted. Within `interval` only a
+    /// single reconnect is allowed.
+    fn reconnect_allowed(&self, interval: Duration) -> bool {
+        if interval.is_zero() {
+            return true;
+        }
+        let now = START.elapsed().as_nanos() as u64 + 1;
+        claim_reconnect(&self.last_reconnect, now, interval.as_nanos() as u64)
+    }
+
+    /// Open a new socket, send `buffer` and replace the current socket on success.
+    fn reconnect(&self, lock: RwLockUpgradableReadGuard<UnixDatagram>, buffer: &[u8]) -> io::Result<()> {
+        // Try to create an unbounded socket. Expect this to work.
+        let socket = UnixDatagram::unbound()?;
+
+        // Upgrade the read lock and replace the socket if the sent attempt is successful.
+        let mut lock = RwLockUpgradableReadGuard::upgrade(lock);
+        socket.connect(&self.path)?;
+        configure(&socket, crate::write_mode())?;
+        let passcred = set_passcred(&socket).is_ok();
+
+        socket.send(buffer)?;
+        self.passcred.store(passcred, Ordering::Relaxed);
+
+        // Assign the new socket to the lock. Writers that fail in the meantime
+        // reconnect again unless throttled by the reconnect interval.
+        *lock = socket;
+        Ok(())
+    }
+}
+
+/// Claim the reconnect attempt at `now` if the last attempt stored in `last`
+/// is at least `interval` ago.
+fn claim_reconnect(last: &AtomicU64, now: u64, interval: u64) -> bool {
+    let previous = last.load(Ordering::Relaxed);
+    if previous != 0 && now.saturating_sub(previous) < interval {
+        return false;
+    }
+    last.compare_exchange(previous, now, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+}
+
+/// Send `buffer` on `socket` with a `SCM_CREDENTIALS` control message
+/// carrying `uid`. The send does not block unless `blocking` is set.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_with_credentials(socket: &UnixDatagram, buffer: &[u8], uid: u32, blocking: bool) -> io::Result<()> {
+    let flags = if blocking { 0 } else { libc::MSG_DONTWAIT };
+
+    // Safety: getpid and getgid are always successful.
+    let credentials = libc::ucred {
+        pid: unsafe { libc::getpid() },
+        uid,
+        gid: unsafe { libc::getgid() },
+    };
+
+    let mut iov = libc::iovec {
+        iov_base: buffer.as_ptr() as *mut libc::c_void,
+        iov_len: buffer.len(),
+    };
+    // Use u64 as element type to satisfy the alignment of cmsghdr.
+    let mut control = [0u64; 8];
+    // Safety: control is large enough for one ucred control message.
+    let control_len = unsafe { libc::CMSG_SPACE(mem::size_of::<libc::ucred>() as u32) } as usize;
+    debug_assert!(control_len <= mem::size_of_val(&control));
+
+    // Safety: the message header is valid for zero initialization and all
+    // pointers reference buffers that outlive the sendmsg call.
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control_len as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_CREDENTIALS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::ucred>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::ucred, credentials);
+
+        if libc::sendmsg(socket.as_raw_fd(), &msg, flags) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Credentials cannot be passed on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn send_with_credentials(_: &UnixDatagram, _: &[u8], _: u32, _: bool) -> io::Result<()> {
+    Err(io::Error::from(ErrorKind::Unsupported))
+}
+
+/// Set `SO_PASSCRED` on `socket`. The kernel then attaches the credentials
+/// of the process to each datagram regardless of the setting of the peer.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_passcred(socket: &UnixDatagram) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    // Safety: the option value is a valid c_int that outlives the call.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            &enable as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Credentials cannot be passed on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_passcred(_: &UnixDatagram) -> io::Result<()> {
+    Err(io::Error::from(ErrorKind::Unsupported))
+}
+
+/// Configure `socket` for `mode`.
+///
+/// The logd socket is a datagram socket. If a write fails the logd might be
+/// under heavy load and is unable to process this write. In discard mode the
+/// socket is non blocking and the write is discarded. In blocking mode the
+/// write waits up to the timeout set with `SO_SNDTIMEO`.
+fn configure(socket: &UnixDatagram, mode: WriteMode) -> io::Result<()> {
+    match mode {
+        WriteMode::Discard => socket.set_nonblocking(true),
+        WriteMode::Blocking { timeout } => {
+            socket.set_nonblocking(false)?;
+            // A zero timeout is rejected. Block without timeout instead.
+            socket.set_write_timeout(Some(timeout).filter(|timeout| !timeout.is_zero()))
+        }
+    }
+}
+
+/// Apply `mode` to all open sockets.
+pub(crate) fn set_write_mode(mode: WriteMode) {
+    if let Err(e) = SOCKET.set_write_mode(mode) {
+        eprintln!("Failed to set the write mode of the logd socket: {}", e);
+    }
+    for (_, socket) in BUFFER_SOCKETS.read().iter() {
+        if let Err(e) = socket.set_write_mode(mode) {
+            eprintln!("Failed to set the write mode of the logd socket: {}", e);
+        }
+    }
+}
+
+/// Use the socket at `path` for writes to `buffer`.
+pub(crate) fn set_buffer_socket(buffer: Buffer, path: &Path) {
+    let id = buffer.into();
+    let socket = Arc::new(LogdSocket::connect(path));
+    let mut sockets = BUFFER_SOCKETS.write();
+    match sockets.iter_mut().find(|(b, _)| *b == id) {
+        Some((_, s)) => *s = socket,
+        None => sockets.push((id, socket)),
+    }
+}
+
+/// Call `f` with the socket used for writes to `buffer`.
+fn with_socket<R>(buffer: Buffer, f: impl FnOnce(&LogdSocket) -> R) -> R {
+    let id: u8 = buffer.into();
+    let sockets = BUFFER_SOCKETS.read();
+    match sockets.iter().find(|(b, _)| *b == id) {
+        Some((_, socket)) => f(socket),
+        None => f(&SOCKET),
+    }
+}
+
+/// Snapshot of the logd socket counters summed over all sockets.
+pub(crate) fn counters() -> Counters {
+    BUFFER_SOCKETS
+        .read()
+        .iter()
+        .map(|(_, socket)| socket.counters())
+        .fold(SOCKET.counters(), |sum, counters| Counters {
+            sent: sum.sent + counters.sent,
+            dropped: sum.dropped + counters.dropped,
+            reconnects: sum.reconnects + counters.reconnects,
+        })
 }
 
 /// Send a log message to logd
-pub(crate) fn log(record: &Record) {
+///
+/// The datagrams of a chunked message are sent until the first failure. The
+/// failure is returned once per record. In dry run mode the datagrams are
+/// encoded and validated but not sent.
+pub(crate) fn log(record: &Record) -> Result<(), Error> {
+    if crate::dry_run() {
+        return datagrams(record).try_for_each(|buffer| validate(&buffer, Error::RecordSize));
+    }
+
+    let result = with_socket(record.buffer_id, |socket| {
+        if crate::require_passcred() && !socket.passcred() {
+            return Err(Error::Passcred);
+        }
+        datagrams(record).try_for_each(|buffer| {
+            match record.uid {
+                Some(uid) => socket.send_as(&buffer, uid),
+                None => socket.send(&buffer),
+            }
+            .map_err(Error::from)
+        })
+    });
+    if let Err(e) = &result {
+        diagnostics::report("logd", format_args!("failed to send log message: {}", e));
+    }
+    result
+}
+
+/// Encode and validate a log message without sending it.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn validate_record(record: &Record) -> Result<(), Error> {
+    datagrams(record).try_for_each(|buffer| validate(&buffer, Error::RecordSize))
+}
+
+/// Encode a log message into the datagrams sent to logd.
+///
+/// Messages exceeding the maximum entry length are split into chunks like
+/// the pmsg packets. The number of chunks is limited.
+pub(crate) fn datagrams<'a>(record: &Record<'a, 'a>) -> impl Iterator<Item = Bytes> + 'a {
+    let record = *record;
+    let chunks = NewlineScaledChunkIterator::new(record.message, max_message_len(record.tag));
+    limit_chunks(chunks, crate::max_chunks()).map(move |chunk| {
+        let record = Record {
+            message: &chunk,
+            ..record
+        };
+        encode(&record).freeze()
+    })
+}
+
+/// Maximum length of a message chunk with `tag`. The datagram contains the
+/// 12 byte header and the zero terminated tag and message. At least one
+/// character fits.
+fn max_message_len(tag: &str) -> usize {
+    max_entry_len().saturating_sub(12 + tag.len() + 1 + 1).max(4)
+}
+
+/// Encode a log message into a logd datagram.
+fn encode(record: &Record) -> bytes::BytesMut {
     // Tag and message len with null terminator.
-    let tag_len = record.tag.bytes().len() + 1;
-    let message_len = record.message.bytes().len() + 1;
+    let tag_len = record.tag.len() + 1;
+    let message_len = record.message.len() + 1;
     let mut buffer = bytes::BytesMut::with_capacity(12 + tag_len + message_len);
     let timestamp = record.timestamp.duration_since(UNIX_EPOCH).unwrap();
 
+    let order = byte_order();
+
     buffer.put_u8(record.buffer_id.into());
-    buffer.put_u16_le(thread::id() as u16);
-    buffer.put_u32_le(timestamp.as_secs() as u32);
-    buffer.put_u32_le(timestamp.subsec_nanos());
+    order.put_u16(&mut buffer, record.thread_id);
+    order.put_u32(&mut buffer, timestamp.as_secs() as u32);
+    order.put_u32(&mut buffer, timestamp.subsec_nanos());
     buffer.put_u8(record.priority as u8);
     buffer.put(record.tag.as_bytes());
     buffer.put_u8(0);
 
     buffer.put(record.message.as_bytes());
     buffer.put_u8(0);
+    buffer
+}
 
-    if let Err(e) = SOCKET.send(&buffer) {
-        eprintln!("Failed to send log message \"{}: {}\": {}", record.tag, record.message, e);
+/// Check that a datagram does not exceed the logd limit.
+fn validate(buffer: &[u8], error: Error) -> Result<(), Error> {
+    if buffer.len() > max_entry_len() {
+        Err(error)
+    } else {
+        Ok(())
     }
 }
 
 /// Send a log event to logd
-pub(crate) fn write_event(log_buffer: Buffer, event: &Event) {
-    let mut buffer = bytes::BytesMut::with_capacity(LOGGER_ENTRY_MAX_LEN);
-    let timestamp = event.timestamp.duration_since(UNIX_EPOCH).unwrap();
+///
+/// In dry run mode the datagram is encoded and validated but not sent.
+pub(crate) fn write_event(log_buffer: Buffer, event: &Event) -> Result<(), Error> {
+    let buffer = encode_event(log_buffer, thread::id() as u16, event, crate::event_timestamp_unit());
+    send_event_datagram(log_buffer, &buffer)
+}
 
-    buffer.put_u8(log_buffer.into());
-    buffer.put_u16_le(thread::id() as u16);
-    buffer.put_u32_le(timestamp.as_secs() as u32);
-    buffer.put_u32_le(timestamp.subsec_nanos());
-    buffer.put_u32_le(event.tag);
-    buffer.put(event.value.as_bytes());
-    if let Err(e) = SOCKET.send(&buffer) {
-        eprintln!("Failed to write event {:?}: {}", event, e);
+/// Send an encoded event datagram to logd.
+///
+/// In dry run mode the datagram is validated but not sent.
+pub(crate) fn send_event_datagram(log_buffer: Buffer, buffer: &[u8]) -> Result<(), Error> {
+    if crate::dry_run() {
+        return validate(buffer, Error::EventSize);
     }
+
+    if let Err(e) = with_socket(log_buffer, |socket| socket.send(buffer)) {
+        eprintln!("Failed to write event to {:?}: {}", log_buffer, e);
+        diagnostics::report("logd", format_args!("failed to write event: {}", e));
+    }
+    Ok(())
+}
+
+/// Send a log event to logd and wait until the datagram is handed over.
+///
+/// The wait is limited to the timeout of [`WriteMode::Blocking`] or to
+/// [`BLOCKING_EVENT_TIMEOUT`] in [`WriteMode::Discard`]. In dry run mode the
+/// datagram is encoded and validated but not sent.
+pub(crate) fn write_event_blocking(log_buffer: Buffer, event: &Event) -> Result<(), Error> {
+    let buffer = encode_event(log_buffer, thread::id() as u16, event, crate::event_timestamp_unit());
+
+    if crate::dry_run() {
+        return validate(&buffer, Error::EventSize);
+    }
+
+    let timeout = match crate::write_mode() {
+        WriteMode::Discard => BLOCKING_EVENT_TIMEOUT,
+        WriteMode::Blocking { timeout } => timeout,
+    };
+    with_socket(log_buffer, |socket| socket.send_blocking(&buffer, timeout))
+}
+
+/// Encode a log event of the thread `thread_id` into a logd datagram. The
+/// fractional part of the timestamp is written in `unit`.
+fn encode_event(log_buffer: Buffer, thread_id: u16, event: &Event, unit: TimestampUnit) -> bytes::BytesMut {
+    let mut buffer = encode_event_header(log_buffer, thread_id, event.timestamp, event.tag, unit);
+    buffer.put(event.value.encode(byte_order()));
+    buffer
+}
+
+/// Encode the header of an event datagram up to and including the tag.
+pub(crate) fn encode_event_header(
+    log_buffer: Buffer,
+    thread_id: u16,
+    timestamp: SystemTime,
+    tag: EventTag,
+    unit: TimestampUnit,
+) -> bytes::BytesMut {
+    let mut buffer = bytes::BytesMut::with_capacity(max_entry_len());
+    let timestamp = timestamp.duration_since(UNIX_EPOCH).unwrap();
+    let subsec = match unit {
+        TimestampUnit::Nanos => timestamp.subsec_nanos(),
+        TimestampUnit::Micros => timestamp.subsec_micros(),
+    };
+
+    let order = byte_order();
+
+    buffer.put_u8(log_buffer.into());
+    order.put_u16(&mut buffer, thread_id);
+    order.put_u32(&mut buffer, timestamp.as_secs() as u32);
+    order.put_u32(&mut buffer, subsec);
+    order.put_u32(&mut buffer, tag);
+    buffer
 }
 
 #[test]
@@ -134,13 +573,316 @@ fn smoke() {
         let timestamp = SystemTime::now();
         let record = Record {
             timestamp,
-            pid: std::process::id() as u16,
+            pid: std::process::id(),
             thread_id: thread::id() as u16,
             buffer_id: Buffer::Main,
             tag: "test",
             priority: Priority::Info,
             message: "test",
+            uid: None,
         };
-        log(&record);
+        log(&record).ok();
     }
 }
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn credentials() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let receiver = UnixDatagram::bind(&path).unwrap();
+    let sender = UnixDatagram::unbound().unwrap();
+    sender.connect(&path).unwrap();
+
+    // Passing the own credentials does not require privileges.
+    let uid = unsafe { libc::getuid() };
+    send_with_credentials(&sender, b"test", uid, false).unwrap();
+
+    let mut buffer = [0u8; 16];
+    let len = receiver.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"test");
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn passcred() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let _receiver = UnixDatagram::bind(&path).unwrap();
+    let socket = LogdSocket::connect(&path);
+    assert!(socket.passcred());
+
+    let mut enabled: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            socket.socket.read().as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            &mut enabled as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    assert_eq!(result, 0);
+    assert_eq!(enabled, 1);
+}
+
+#[test]
+fn buffer_socket() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let receiver = UnixDatagram::bind(&path).unwrap();
+
+    set_buffer_socket(Buffer::Custom(200), &path);
+    with_socket(Buffer::Custom(200), |socket| socket.send(b"test")).unwrap();
+
+    let mut buffer = [0u8; 16];
+    let len = receiver.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"test");
+}
+
+#[test]
+fn reconnect_throttled() {
+    let last = AtomicU64::new(0);
+    assert!(claim_reconnect(&last, 10, 100));
+    assert!(!claim_reconnect(&last, 50, 100));
+    assert!(!claim_reconnect(&last, 109, 100));
+    assert!(claim_reconnect(&last, 110, 100));
+}
+
+#[test]
+fn blocking() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+
+    let socket = LogdSocket::connect(&path);
+    assert!(socket.send_blocking(b"test", BLOCKING_EVENT_TIMEOUT).is_err());
+
+    let receiver = UnixDatagram::bind(&path).unwrap();
+    socket.send_blocking(b"test", BLOCKING_EVENT_TIMEOUT).unwrap();
+    let mut buffer = [0u8; 16];
+    let len = receiver.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"test");
+    assert_eq!(socket.counters().sent, 1);
+    assert_eq!(socket.counters().dropped, 0);
+}
+
+#[test]
+fn reconnect_once_per_interval() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let socket = Arc::new(LogdSocket::connect(&tempdir.path().join("socket")));
+
+    let threads = (0..8)
+        .map(|_| {
+            let socket = socket.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    socket.send_throttled(b"test", Duration::from_secs(3600)).ok();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+
+    let counters = socket.counters();
+    assert_eq!(counters.reconnects, 1);
+    assert_eq!(counters.dropped, 80);
+}
+
+#[test]
+fn dropped_when_busy() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let _receiver = UnixDatagram::bind(&path).unwrap();
+    let socket = LogdSocket::connect(&path);
+
+    // The receiver never reads and the queue fills up.
+    for _ in 0..10_000 {
+        socket.send(b"test").unwrap();
+    }
+
+    let counters = socket.counters();
+    assert!(counters.dropped > 0);
+    assert_eq!(counters.sent + counters.dropped, 10_000);
+    assert_eq!(counters.reconnects, 0);
+}
+
+#[test]
+fn blocking_timeout() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let _receiver = UnixDatagram::bind(&path).unwrap();
+    let socket = LogdSocket::connect(&path);
+    let timeout = Duration::from_millis(10);
+    socket.set_write_mode(WriteMode::Blocking { timeout }).unwrap();
+
+    // The receiver never reads and the queue fills up.
+    let start = Instant::now();
+    let error = (0..10_000).find_map(|_| socket.send(b"test").err()).unwrap();
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= timeout);
+    assert_eq!(socket.counters().dropped, 1);
+}
+
+#[test]
+fn blocking_event_timeout() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let _receiver = UnixDatagram::bind(&path).unwrap();
+    let socket = LogdSocket::connect(&path);
+    let timeout = Duration::from_millis(10);
+
+    // The receiver never reads and the queue fills up.
+    let start = Instant::now();
+    let error = (0..10_000)
+        .find_map(|_| socket.send_blocking(b"test", timeout).err())
+        .unwrap();
+    match error {
+        Error::Io(e) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+        e => panic!("unexpected error: {}", e),
+    }
+    assert!(start.elapsed() >= timeout);
+    assert_eq!(socket.counters().dropped, 1);
+}
+
+#[test]
+fn validate_size() {
+    use crate::Priority;
+    use std::time::SystemTime;
+
+    let message = "a".repeat(crate::LOGGER_ENTRY_MAX_LEN);
+    let mut record = Record {
+        timestamp: SystemTime::now(),
+        pid: std::process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id: Buffer::Main,
+        tag: "test",
+        priority: Priority::Info,
+        message: &message,
+        uid: None,
+    };
+    assert!(matches!(
+        validate(&encode(&record), Error::RecordSize),
+        Err(Error::RecordSize)
+    ));
+
+    record.message = "test";
+    assert!(validate(&encode(&record), Error::RecordSize).is_ok());
+}
+
+#[test]
+fn chunked() {
+    use crate::Priority;
+    use std::time::SystemTime;
+
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("socket");
+    let receiver = UnixDatagram::bind(&path).unwrap();
+    receiver.set_nonblocking(true).unwrap();
+    set_buffer_socket(Buffer::Custom(230), &path);
+
+    let message = "0123456789abcdef\n".repeat(20 * 1024 / 17 + 1);
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: std::process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id: Buffer::Custom(230),
+        tag: "test",
+        priority: Priority::Info,
+        message: &message,
+        uid: None,
+    };
+    log(&record).unwrap();
+
+    let mut datagrams = Vec::new();
+    let mut buffer = vec![0u8; 2 * max_entry_len()];
+    while let Ok(len) = receiver.recv(&mut buffer) {
+        datagrams.push(buffer[..len].to_vec());
+    }
+    assert_eq!(datagrams.len(), 5);
+    assert!(datagrams.iter().all(|datagram| datagram.len() <= max_entry_len()));
+
+    // Header, tag and terminators are stripped from each datagram.
+    let header_len = 12 + record.tag.len() + 1;
+    let messages = datagrams
+        .iter()
+        .map(|datagram| std::str::from_utf8(&datagram[header_len..datagram.len() - 1]).unwrap())
+        .collect::<Vec<_>>();
+    assert!(messages.iter().all(|message| message.ends_with('\n')));
+    assert_eq!(messages.concat(), message);
+}
+
+#[test]
+fn chunks_stop_on_failure() {
+    use crate::Priority;
+    use std::time::SystemTime;
+
+    let tempdir = tempfile::tempdir().unwrap();
+    set_buffer_socket(Buffer::Custom(231), &tempdir.path().join("socket"));
+
+    let message = "a".repeat(3 * max_entry_len());
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: std::process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id: Buffer::Custom(231),
+        tag: "test",
+        priority: Priority::Info,
+        message: &message,
+        uid: None,
+    };
+    assert!(datagrams(&record).count() > 1);
+    assert!(log(&record).is_err());
+
+    // Only the first datagram is attempted.
+    let counters = with_socket(Buffer::Custom(231), |socket| socket.counters());
+    assert_eq!(counters.sent, 0);
+    assert_eq!(counters.dropped, 1);
+}
+
+#[test]
+fn thread_id() {
+    use crate::Priority;
+    use std::time::SystemTime;
+
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: std::process::id(),
+        thread_id: 0xbeef,
+        buffer_id: Buffer::Main,
+        tag: "test",
+        priority: Priority::Info,
+        message: "test",
+        uid: None,
+    };
+    let mut expected = bytes::BytesMut::new();
+    byte_order().put_u16(&mut expected, 0xbeef);
+    assert_eq!(encode(&record)[1..3], expected[..]);
+
+    let event = Event {
+        timestamp: SystemTime::now(),
+        tag: 1,
+        value: crate::EventValue::Void,
+    };
+    let buffer = encode_event(Buffer::Events, 0xbeef, &event, TimestampUnit::Nanos);
+    assert_eq!(buffer[1..3], expected[..]);
+}
+
+#[test]
+fn event_timestamp_unit() {
+    use std::time::Duration;
+
+    let event = Event {
+        timestamp: UNIX_EPOCH + Duration::new(1, 123_456_789),
+        tag: 1,
+        value: crate::EventValue::Void,
+    };
+
+    let nanos = encode_event(Buffer::Events, 1, &event, TimestampUnit::Nanos);
+    assert_eq!(nanos[3..7], 1u32.to_le_bytes());
+    assert_eq!(nanos[7..11], 123_456_789u32.to_le_bytes());
+
+    let micros = encode_event(Buffer::Events, 1, &event, TimestampUnit::Micros);
+    assert_eq!(micros[3..7], 1u32.to_le_bytes());
+    assert_eq!(micros[7..11], 123_456u32.to_le_bytes());
+}
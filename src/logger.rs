@@ -1,26 +1,250 @@
-use crate::{thread, Buffer, Priority, Record, TagMode};
-use env_logger::filter::{Builder, Filter};
-use log::{LevelFilter, Log, Metadata};
-use parking_lot::RwLock;
-use std::{io, process, sync::Arc, time::SystemTime};
+use crate::{
+    filter::Directives,
+    logging_iterator::truncate_on_char_boundary,
+    rate_limit::{suppressed_message, RateLimiter},
+    repeats::{Push, Repeated, Repeats},
+    ring_buffer::RingBuffer,
+    stats::StatsEmitter,
+    thread, Buffer, CapturedRecord, ClockSource, ContextFormat, Error, Priority, Record, TagMode, TagModeDescriptor,
+    TimestampFormat, VersionPosition,
+};
+use env_logger::filter::Filter;
+use log::{Level, LevelFilter, Log, Metadata};
+use parking_lot::{Mutex, RwLock, RwLockWriteGuard};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt, io,
+    ops::{Deref, DerefMut},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread::Builder as ThreadBuilder,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Escape character that starts ANSI escape sequences.
+const ESC: char = '\x1b';
+
+/// UTF-8 byte order mark.
+const BOM: char = '\u{feff}';
+
+/// Message of the record written by [`Logger::shutdown`].
+const SHUTDOWN_MESSAGE: &str = "logger shutting down";
+
+/// Maximum tag length of `logcat` before Android 8.
+const TAG_MAX_LEN: usize = 23;
+
+/// Callback invoked with errors of writing a record. See [`Builder::on_error`](crate::Builder::on_error).
+pub(crate) type ErrorHandler = Arc<dyn Fn(&Error) + Send + Sync>;
+
+/// Buffer of records of a level. See [`Builder::route_level`](crate::Builder::route_level).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LevelRoute {
+    pub(crate) level: Level,
+    pub(crate) buffer: Buffer,
+    /// Write to `buffer` in addition to the buffer of the logger.
+    pub(crate) additional: bool,
+}
+
+/// Callback rendering the message of a record. See [`Builder::format`](crate::Builder::format).
+pub(crate) type Formatter = Arc<dyn Fn(&log::Record) -> String + Send + Sync>;
+
+/// Number of records dropped because they were logged from an error handler.
+static DROPPED_IN_ERROR_HANDLER: AtomicU64 = AtomicU64::new(0);
+
+/// Flush request of [`Logger::flush`] with a timeout: the configuration and
+/// the sender of the result.
+type FlushRequest = (Arc<RwLock<Configuration>>, mpsc::Sender<bool>);
+
+/// Sender of the requests to the flush worker thread. The worker is spawned on
+/// the first flush with a timeout and reused by later flushes.
+static FLUSH_WORKER: Mutex<Option<mpsc::Sender<FlushRequest>>> = parking_lot::const_mutex(None);
+
+/// Incremented on each change of a logger configuration. Invalidates the
+/// snapshots of the lock free hot path.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Set while the error handler runs on this thread.
+    static IN_ERROR_HANDLER: Cell<bool> = const { Cell::new(false) };
+
+    /// Errors to pass to the error handler once the configuration is unlocked.
+    static PENDING_ERRORS: RefCell<Vec<(ErrorHandler, Error)>> = const { RefCell::new(Vec::new()) };
+
+    /// Snapshot of a configuration used by the lock free hot path of this
+    /// thread with the address of the configuration and the generation it
+    /// was taken at.
+    static SNAPSHOT: RefCell<Option<(usize, u64, Arc<Configuration>)>> = const { RefCell::new(None) };
+}
+
+/// Returns the number of records dropped because they were logged from an error handler.
+pub(crate) fn dropped_in_error_handler() -> u64 {
+    DROPPED_IN_ERROR_HANDLER.load(Ordering::Relaxed)
+}
 
 /// Logger configuration.
 pub(crate) struct Configuration {
     pub(crate) filter: Filter,
+    pub(crate) directives: Directives,
     pub(crate) tag: TagMode,
     pub(crate) prepend_module: bool,
+    pub(crate) format: Option<Formatter>,
+    pub(crate) timestamp_format: TimestampFormat,
     #[allow(unused)]
     pub(crate) pstore: bool,
     pub(crate) buffer_id: Buffer,
+    pub(crate) level_routes: Vec<LevelRoute>,
+    pub(crate) mirror_buffers: Vec<Buffer>,
+    #[cfg(feature = "pmsg-compress")]
+    #[allow(unused)]
+    pub(crate) pmsg_compress: bool,
+    #[cfg(feature = "atrace")]
+    #[allow(unused)]
+    pub(crate) atrace: bool,
+    #[allow(unused)]
+    pub(crate) pmsg_clock: ClockSource,
+    #[allow(unused)]
+    pub(crate) pstore_min_level: LevelFilter,
+    pub(crate) stats_emitter: Option<StatsEmitter>,
+    pub(crate) allow_self_logs: bool,
+    pub(crate) embed_priority: bool,
+    pub(crate) strip_ansi: bool,
+    pub(crate) strip_bom: bool,
+    pub(crate) context_format: Option<ContextFormat>,
+    pub(crate) log_shutdown: bool,
+    pub(crate) version: Option<&'static str>,
+    pub(crate) version_position: VersionPosition,
+    pub(crate) ring_buffer: Option<Arc<RingBuffer>>,
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+    pub(crate) repeats: Option<Arc<Repeats>>,
+    pub(crate) on_error: Option<ErrorHandler>,
+    pub(crate) flush_timeout: Option<Duration>,
+    #[cfg(feature = "kv")]
+    pub(crate) kv_event: Option<crate::EventTag>,
+    #[cfg(feature = "kv")]
+    pub(crate) render_kv: bool,
+    #[cfg(feature = "kv")]
+    pub(crate) honor_ts_field: bool,
+}
+
+impl Configuration {
+    /// Returns true if the target passes the self log guard.
+    fn target_allowed(&self, target: &str) -> bool {
+        self.allow_self_logs || !is_self_target(target)
+    }
+
+    /// Returns a copy of the configuration for the lock free hot path.
+    ///
+    /// The copy shares the ring buffer and does not own the stats emitter.
+    fn snapshot(&self) -> Configuration {
+        Configuration {
+            filter: self.directives.build(),
+            directives: self.directives.clone(),
+            tag: self.tag.clone(),
+            prepend_module: self.prepend_module,
+            format: self.format.clone(),
+            timestamp_format: self.timestamp_format.clone(),
+            pstore: self.pstore,
+            buffer_id: self.buffer_id,
+            level_routes: self.level_routes.clone(),
+            mirror_buffers: self.mirror_buffers.clone(),
+            #[cfg(feature = "pmsg-compress")]
+            pmsg_compress: self.pmsg_compress,
+            #[cfg(feature = "atrace")]
+            atrace: self.atrace,
+            pmsg_clock: self.pmsg_clock,
+            pstore_min_level: self.pstore_min_level,
+            stats_emitter: None,
+            allow_self_logs: self.allow_self_logs,
+            embed_priority: self.embed_priority,
+            strip_ansi: self.strip_ansi,
+            strip_bom: self.strip_bom,
+            context_format: self.context_format,
+            log_shutdown: self.log_shutdown,
+            version: self.version,
+            version_position: self.version_position,
+            ring_buffer: self.ring_buffer.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            repeats: self.repeats.clone(),
+            on_error: self.on_error.clone(),
+            flush_timeout: self.flush_timeout,
+            #[cfg(feature = "kv")]
+            kv_event: self.kv_event,
+            #[cfg(feature = "kv")]
+            render_kv: self.render_kv,
+            #[cfg(feature = "kv")]
+            honor_ts_field: self.honor_ts_field,
+        }
+    }
+
+    /// Replaces the filter directives and rebuilds the filter.
+    fn set_directives(&mut self, directives: Directives) {
+        self.filter = directives.build();
+        self.directives = directives;
+    }
+
+    /// Replaces the filter directives with the parsed `filters` and updates
+    /// the maximum level of the `log` crate.
+    fn parse_directives(&mut self, filters: &str) {
+        let mut directives = Directives::default();
+        directives.parse(filters);
+        self.set_directives(directives);
+        log::set_max_level(self.filter.filter());
+    }
+
+    /// Merges a directive into the filter directives and updates the
+    /// maximum level of the `log` crate.
+    fn insert_directive(&mut self, module: Option<&str>, level: LevelFilter) {
+        let mut directives = self.directives.clone();
+        directives.insert(module, level);
+        self.set_directives(directives);
+        log::set_max_level(self.filter.filter());
+    }
+}
+
+/// Write access to a configuration. Invalidates the snapshots of the lock
+/// free hot path when dropped.
+pub(crate) struct ConfigurationWriteGuard<'a>(RwLockWriteGuard<'a, Configuration>);
+
+impl Deref for ConfigurationWriteGuard<'_> {
+    type Target = Configuration;
+
+    fn deref(&self) -> &Configuration {
+        &self.0
+    }
+}
+
+impl DerefMut for ConfigurationWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Configuration {
+        &mut self.0
+    }
+}
+
+impl Drop for ConfigurationWriteGuard<'_> {
+    fn drop(&mut self) {
+        // The write lock is released after this increment. Snapshots taken
+        // for the new generation wait for the lock and see the change.
+        GENERATION.fetch_add(1, Ordering::Release);
+    }
 }
 
 /// Logger configuration handler stores access to logger configuration parameters.
 #[derive(Clone)]
 pub struct Logger {
     pub(crate) configuration: Arc<RwLock<Configuration>>,
+    /// Level and deadline of a pending [`Logger::filter_level_debounced`].
+    pub(crate) pending_level: Arc<Mutex<Option<(LevelFilter, Instant)>>>,
 }
 
 impl Logger {
+    /// Locks the configuration for writing.
+    pub(crate) fn write(&self) -> ConfigurationWriteGuard<'_> {
+        ConfigurationWriteGuard(self.configuration.write())
+    }
+
     /// Sets buffer parameter of logger configuration
     ///
     /// # Examples
@@ -34,7 +258,7 @@ impl Logger {
     /// logger.buffer(Buffer::Crash);
     /// ```
     pub fn buffer(&self, buffer: Buffer) -> &Self {
-        self.configuration.write().buffer_id = buffer;
+        self.write().buffer_id = buffer;
         self
     }
 
@@ -51,7 +275,7 @@ impl Logger {
     /// logger.tag("foo");
     /// ```
     pub fn tag(&self, tag: &str) -> &Self {
-        self.configuration.write().tag = TagMode::Custom(tag.into());
+        self.write().tag = TagMode::Custom(tag.into());
         self
     }
 
@@ -68,7 +292,7 @@ impl Logger {
     /// logger.tag_target();
     /// ```
     pub fn tag_target(&self) -> &Self {
-        self.configuration.write().tag = TagMode::Target;
+        self.write().tag = TagMode::Target;
         self
     }
 
@@ -85,7 +309,7 @@ impl Logger {
     /// logger.tag_target_strip();
     /// ```
     pub fn tag_target_strip(&self) -> &Self {
-        self.configuration.write().tag = TagMode::TargetStrip;
+        self.write().tag = TagMode::TargetStrip;
         self
     }
 
@@ -102,12 +326,51 @@ impl Logger {
     /// logger.prepend_module(true);
     /// ```
     pub fn prepend_module(&self, prepend_module: bool) -> &Self {
-        self.configuration.write().prepend_module = prepend_module;
+        self.write().prepend_module = prepend_module;
+        self
+    }
+
+    /// Sets the format of timestamps in records printed to stderr.
+    ///
+    /// See [`Builder::timestamp_format`](crate::Builder::timestamp_format).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timestamp`] if `format` is not a valid format description.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.timestamp_format("[hour]:[minute]:[second]").unwrap();
+    /// ```
+    pub fn timestamp_format(&self, format: &str) -> Result<&Self, Error> {
+        let format = TimestampFormat::parse(format)?;
+        self.write().timestamp_format.format = Some(format);
+        Ok(self)
+    }
+
+    /// Prints timestamps in records printed to stderr in local time.
+    ///
+    /// See [`Builder::local_time`](crate::Builder::local_time).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.local_time(true);
+    /// ```
+    pub fn local_time(&self, local_time: bool) -> &Self {
+        self.write().timestamp_format.local = local_time;
         self
     }
 
     /// Adds a directive to the filter for a specific module.
     ///
+    /// The directives of other modules and the global level are retained.
+    ///
     /// # Examples
     ///
     /// Only include messages for warning and above for logs in `path::to::module`:
@@ -121,12 +384,66 @@ impl Logger {
     /// logger.filter_module("path::to::module", LevelFilter::Info);
     /// ```
     pub fn filter_module(&self, module: &str, level: LevelFilter) -> &Self {
-        self.configuration.write().filter = Builder::default().filter_module(module, level).build();
+        self.write().insert_directive(Some(module), level);
+        self
+    }
+
+    /// Sets the filter level after a quiet period of `delay`.
+    ///
+    /// Each call replaces a pending level and restarts the delay. The last
+    /// level is applied once no call was made for `delay`. This coalesces
+    /// rapid changes, e.g. of a verbosity slider, into a single
+    /// reconfiguration. The level is applied on a separate thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    /// # use std::time::Duration;
+    ///
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// for level in [LevelFilter::Trace, LevelFilter::Debug, LevelFilter::Info] {
+    ///     logger.filter_level_debounced(level, Duration::from_millis(100));
+    /// }
+    /// ```
+    pub fn filter_level_debounced(&self, level: LevelFilter, delay: Duration) -> &Self {
+        let mut pending = self.pending_level.lock();
+        let running = pending.is_some();
+        *pending = Some((level, Instant::now() + delay));
+        if running {
+            return self;
+        }
+
+        let logger = self.clone();
+        let spawned = ThreadBuilder::new().name("logd-debounce".into()).spawn(move || loop {
+            let mut pending = logger.pending_level.lock();
+            match *pending {
+                Some((level, deadline)) if deadline <= Instant::now() => {
+                    // Apply with the lock held so that a later call cannot
+                    // be overtaken by this one.
+                    logger.filter_level(level);
+                    *pending = None;
+                    break;
+                }
+                Some((_, deadline)) => {
+                    drop(pending);
+                    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                }
+                None => break,
+            }
+        });
+        if spawned.is_err() {
+            *pending = None;
+            self.filter_level(level);
+        }
         self
     }
 
     /// Adjust filter.
     ///
+    /// Sets the global level. Module directives are retained.
+    ///
     /// # Examples
     ///
     /// Only include messages for warning and above.
@@ -139,7 +456,7 @@ impl Logger {
     /// logger.filter_level(LevelFilter::Info);
     /// ```
     pub fn filter_level(&self, level: LevelFilter) -> &Self {
-        self.configuration.write().filter = Builder::default().filter_level(level).build();
+        self.write().insert_directive(None, level);
         self
     }
 
@@ -147,6 +464,7 @@ impl Logger {
     ///
     /// The given module (if any) will log at most the specified level provided.
     /// If no module is provided then the filter will apply to all log messages.
+    /// All other directives are retained.
     ///
     /// # Examples
     ///
@@ -160,7 +478,7 @@ impl Logger {
     /// logger.filter(Some("path::to::module"), LevelFilter::Info);
     /// ```
     pub fn filter(&self, module: Option<&str>, level: LevelFilter) -> &Self {
-        self.configuration.write().filter = Builder::default().filter(module, level).build();
+        self.write().insert_directive(module, level);
         self
     }
 
@@ -169,12 +487,127 @@ impl Logger {
     ///
     /// See the module documentation for more details.
     pub fn parse_filters(&mut self, filters: &str) -> &mut Self {
-        let filter = Builder::default().parse(filters).build();
-        log::set_max_level(filter.filter());
-        self.configuration.write().filter = filter;
+        self.write().parse_directives(filters);
+        self
+    }
+
+    /// Replaces the filter with the directives of the environment variable
+    /// `var`, parsed like [`parse_filters`](Logger::parse_filters).
+    ///
+    /// Returns false and leaves the filter unchanged if `var` is not set or
+    /// not unicode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// if !logger.reload_from_env("RUST_LOG") {
+    ///     log::warn!("RUST_LOG is not set");
+    /// }
+    /// ```
+    pub fn reload_from_env(&self, var: &str) -> bool {
+        match std::env::var(var) {
+            Ok(filters) => {
+                self.write().parse_directives(&filters);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Sets the level of a single module.
+    ///
+    /// Same as [`filter_module`](Logger::filter_module): a directive for
+    /// `module` is replaced, all other directives are retained.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    ///
+    /// let logger = android_logd_logger::builder().parse_filters("info").init();
+    ///
+    /// logger.set_module_level("path::to::module", LevelFilter::Error);
+    /// ```
+    pub fn set_module_level(&self, module: &str, level: LevelFilter) -> &Self {
+        self.write().insert_directive(Some(module), level);
         self
     }
 
+    /// Returns the module specific levels of the filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    ///
+    /// let logger = android_logd_logger::builder().parse_filters("info,foo=debug").init();
+    ///
+    /// assert_eq!(logger.module_levels(), vec![("foo".to_string(), LevelFilter::Debug)]);
+    /// ```
+    pub fn module_levels(&self) -> Vec<(String, LevelFilter)> {
+        self.configuration.read().directives.modules()
+    }
+
+    /// Returns the most verbose level enabled by the filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    ///
+    /// let logger = android_logd_logger::builder().parse_filters("info,foo=debug").init();
+    ///
+    /// assert_eq!(logger.level(), LevelFilter::Debug);
+    /// ```
+    pub fn level(&self) -> LevelFilter {
+        self.configuration.read().filter.filter()
+    }
+
+    /// Returns the buffer records are written to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::Buffer;
+    ///
+    /// let logger = android_logd_logger::builder().buffer(Buffer::Crash).init();
+    ///
+    /// assert_eq!(logger.buffer_id(), Buffer::Crash);
+    /// ```
+    pub fn buffer_id(&self) -> Buffer {
+        self.configuration.read().buffer_id
+    }
+
+    /// Returns the tag mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::TagModeDescriptor;
+    ///
+    /// let logger = android_logd_logger::builder().tag("foo").init();
+    ///
+    /// assert_eq!(logger.tag_mode(), TagModeDescriptor::Custom("foo".into()));
+    /// ```
+    pub fn tag_mode(&self) -> TagModeDescriptor {
+        (&self.configuration.read().tag).into()
+    }
+
+    /// Returns true if records are written to the pstore.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// assert!(logger.pstore_enabled());
+    /// ```
+    pub fn pstore_enabled(&self) -> bool {
+        self.configuration.read().pstore
+    }
+
     /// Sets filter parameter of logger configuration
     ///
     /// # Examples
@@ -189,87 +622,1158 @@ impl Logger {
     /// ```
     #[cfg(target_os = "android")]
     pub fn pstore(&self, pstore: bool) -> &Self {
-        self.configuration.write().pstore = pstore;
+        self.write().pstore = pstore;
         self
     }
+
+    /// Sets the minimum level of records written to the pstore.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::LevelFilter;
+    ///
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.pstore_min_level(LevelFilter::Warn);
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn pstore_min_level(&self, level: LevelFilter) -> &Self {
+        self.write().pstore_min_level = level;
+        self
+    }
+
+    /// Stops the periodic stats event started with
+    /// [`Builder::stats_event`](crate::Builder::stats_event).
+    ///
+    /// Blocks until the emitting thread terminated. This is a no-op if no
+    /// stats event is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    ///
+    /// let logger = android_logd_logger::builder()
+    ///     .stats_event(1000, Duration::from_secs(60))
+    ///     .init();
+    ///
+    /// logger.stop_stats_event();
+    /// ```
+    pub fn stop_stats_event(&self) {
+        let emitter = self.write().stats_emitter.take();
+        if let Some(emitter) = emitter {
+            emitter.stop();
+        }
+    }
+
+    /// Closes and reopens the pmsg device.
+    ///
+    /// The handle of the pmsg device becomes stale if the pstore is remounted
+    /// or the device node is recreated. Writes reopen the device once on
+    /// failure, this function allows to reopen it explicitly.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.reopen_pmsg().expect("failed to reopen the pmsg device");
+    /// ```
+    #[cfg(target_os = "android")]
+    pub fn reopen_pmsg(&self) -> io::Result<()> {
+        crate::pmsg::reopen()
+    }
+
+    /// Returns the records retained in memory, oldest first.
+    ///
+    /// Empty unless enabled with [`Builder::ring_buffer`](crate::Builder::ring_buffer).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().ring_buffer(10).init();
+    ///
+    /// log::warn!("something happened");
+    ///
+    /// for record in logger.recent() {
+    ///     println!("{} {}: {}", record.priority, record.tag, record.message);
+    /// }
+    /// ```
+    pub fn recent(&self) -> Vec<CapturedRecord> {
+        self.configuration
+            .read()
+            .ring_buffer
+            .as_ref()
+            .map(|ring_buffer| ring_buffer.records())
+            .unwrap_or_default()
+    }
+
+    /// Returns the running total of a counter written with
+    /// [`write_counter_event`](crate::write_counter_event).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// android_logd_logger::write_counter_event(1, "retries", 1).unwrap();
+    /// assert_eq!(logger.counter_value("retries"), Some(1));
+    /// ```
+    pub fn counter_value(&self, name: &str) -> Option<i64> {
+        crate::counter::value(name)
+    }
+
+    /// Returns the number of records and events discarded since start.
+    ///
+    /// Datagrams are discarded if the logd socket is busy or cannot be
+    /// reconnected. Records logged from the [`on_error`](crate::Builder::on_error)
+    /// callback are counted as well. Only the logd socket on Android
+    /// maintains the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// println!("dropped {} records", logger.dropped_count());
+    /// ```
+    pub fn dropped_count(&self) -> u64 {
+        crate::stats::counters().dropped
+    }
+
+    /// Returns the number of attempts to reconnect to logd since start.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// println!("reconnected {} times", logger.reconnect_count());
+    /// ```
+    pub fn reconnect_count(&self) -> u64 {
+        crate::stats::counters().reconnects
+    }
+
+    /// Logs multiple records with a single acquisition of the configuration lock.
+    ///
+    /// The records logged via the [`Batch`] handle passed to `f` are filtered
+    /// and encoded with a copy of the configuration taken when `batch` is
+    /// called. The lock is released before `f` runs: reconfigurations of the
+    /// logger do not block and apply to records logged after `batch` returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::Level;
+    ///
+    /// let logger = android_logd_logger::builder().parse_filters("info").init();
+    ///
+    /// logger.batch(|b| {
+    ///     for row in 0..10 {
+    ///         b.log(Level::Info, "table", format_args!("row {}", row));
+    ///     }
+    /// });
+    /// ```
+    pub fn batch<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Batch) -> R,
+    {
+        let batch = Batch {
+            configuration: self.configuration.read().snapshot(),
+        };
+        f(&batch)
+    }
+
+    /// Logs a message on behalf of the app with the UID `uid`.
+    ///
+    /// The record is subject to the configured filter. The UID is written to
+    /// the pmsg header and passed as credentials to logd. Passing foreign
+    /// credentials to logd requires `CAP_SETUID`. Without the capability the
+    /// message is attributed to the UID of the process.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use log::Level;
+    ///
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.log_as_uid(10123, Level::Info, "relay", format_args!("forwarded message"));
+    /// ```
+    pub fn log_as_uid(&self, uid: u32, level: Level, target: &str, args: fmt::Arguments) {
+        let record = log::Record::builder().level(level).target(target).args(args).build();
+        log_with_configuration(&self.configuration.read(), &record, Some(uid));
+        report_pending_errors();
+    }
+
+    /// Logs `message` with the given tag, priority and buffer.
+    ///
+    /// The record is written as is. The configuration of the logger including
+    /// the filter and the timestamp format is not applied and not locked.
+    /// This allows forwarding messages of many sources with a tag per message
+    /// without changing the tag of the logger with [`Logger::tag`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use android_logd_logger::{Buffer, Priority};
+    ///
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.log_with("wifi", Priority::Info, Buffer::Main, "connected").unwrap();
+    /// logger.log_with("audio", Priority::Warn, Buffer::Main, "underrun").unwrap();
+    /// ```
+    pub fn log_with(&self, tag: &str, priority: Priority, buffer: Buffer, message: &str) -> Result<(), Error> {
+        if !crate::writable() {
+            return Ok(());
+        }
+        let record = Record {
+            timestamp: SystemTime::now(),
+            pid: process::id(),
+            thread_id: thread::id() as u16,
+            buffer_id: buffer,
+            tag,
+            priority: crate::default_priority(priority),
+            message,
+            uid: None,
+        };
+        crate::log_record(&record, &TimestampFormat::default())
+    }
+
+    /// Flushes the logger and returns true if the flush completed.
+    ///
+    /// On Android the pmsg device is flushed if enabled, on all platforms the
+    /// [target writer](crate::Builder::target_writer) or stderr. This is the
+    /// flush of the [`log::Log`] implementation, e.g. to persist the pstore
+    /// before a planned reboot. With a timeout set with
+    /// [`Builder::flush_timeout`](crate::Builder::flush_timeout) the flush runs
+    /// on a worker thread and false is returned if it does not complete in
+    /// time. A single worker is shared by all flushes: a flush that times out
+    /// keeps running and delays the following flushes. Records are written to
+    /// logd synchronously and need no flush.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    ///
+    /// let logger = android_logd_logger::builder().flush_timeout(Duration::from_secs(1)).init();
+    ///
+    /// log::error!("shutting down");
+    /// assert!(logger.flush());
+    /// ```
+    pub fn flush(&self) -> bool {
+        let timeout = match self.configuration.read().flush_timeout {
+            Some(timeout) => timeout,
+            None => return flush(&self.configuration).is_ok(),
+        };
+
+        let (done, flushed) = mpsc::channel();
+        let sent = {
+            let mut worker = FLUSH_WORKER.lock();
+            if worker.is_none() {
+                *worker = spawn_flush_worker();
+            }
+            worker
+                .as_ref()
+                .is_some_and(|worker| worker.send((self.configuration.clone(), done)).is_ok())
+        };
+        sent && flushed.recv_timeout(timeout).unwrap_or(false)
+    }
+
+    /// Stops the stats event and flushes the logger.
+    ///
+    /// If enabled with [`Builder::log_shutdown`](crate::Builder::log_shutdown)
+    /// the record "logger shutting down" is written with [`Priority::Info`]
+    /// before the flush. The record is written to logd and the pstore
+    /// regardless of the filter and marks a clean shutdown. Returns true if
+    /// the flush completed, see [`Logger::flush`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let logger = android_logd_logger::builder().log_shutdown(true).init();
+    ///
+    /// logger.shutdown();
+    /// ```
+    pub fn shutdown(&self) -> bool {
+        self.stop_stats_event();
+        {
+            let configuration = self.configuration.read();
+            if configuration.log_shutdown {
+                log_shutdown(&configuration);
+            }
+        }
+        report_pending_errors();
+        self.flush()
+    }
+
+    /// Flushes the logger without blocking the calling task.
+    ///
+    /// The flush of the pmsg device runs on a separate thread. The returned
+    /// future completes once the flush is done and does not depend on a
+    /// specific async runtime.
+    ///
+    /// Requires the `async` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn shutdown() -> std::io::Result<()> {
+    /// let logger = android_logd_logger::builder().init();
+    ///
+    /// logger.flush_async().await
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn flush_async(&self) -> impl std::future::Future<Output = io::Result<()>> {
+        let configuration = self.configuration.clone();
+        crate::flush_future::spawn(move || flush(&configuration))
+    }
+}
+
+/// Handle for logging multiple records with one configuration.
+///
+/// See [`Logger::batch`].
+pub struct Batch {
+    configuration: Configuration,
+}
+
+impl Batch {
+    /// Logs a message with the given level and target.
+    ///
+    /// The record is subject to the configured filter.
+    pub fn log(&self, level: Level, target: &str, args: fmt::Arguments) {
+        self.log_record(&log::Record::builder().level(level).target(target).args(args).build());
+    }
+
+    /// Logs a `log` record.
+    ///
+    /// The record is subject to the configured filter.
+    pub fn log_record(&self, record: &log::Record) {
+        log_with_configuration(&self.configuration, record, None);
+        report_pending_errors();
+    }
 }
 
 /// Logger implementation.
 pub(crate) struct LoggerImpl {
     configuration: Arc<RwLock<Configuration>>,
+    lockfree: bool,
 }
 
 impl LoggerImpl {
-    pub fn new(configuration: Arc<RwLock<Configuration>>) -> Result<LoggerImpl, io::Error> {
-        Ok(LoggerImpl { configuration })
+    pub fn new(configuration: Arc<RwLock<Configuration>>, lockfree: bool) -> Result<LoggerImpl, io::Error> {
+        Ok(LoggerImpl { configuration, lockfree })
+    }
+
+    /// Calls `f` with the configuration. In lock free mode `f` is called with
+    /// a thread local snapshot that is only renewed after a change of the
+    /// configuration.
+    fn with_configuration<R>(&self, f: impl FnOnce(&Configuration) -> R) -> R {
+        if !self.lockfree {
+            return f(&self.configuration.read());
+        }
+
+        let id = Arc::as_ptr(&self.configuration) as usize;
+        let generation = GENERATION.load(Ordering::Acquire);
+        let snapshot = SNAPSHOT.with(|snapshot| {
+            let mut snapshot = snapshot.borrow_mut();
+            match &*snapshot {
+                Some((i, g, configuration)) if *i == id && *g == generation => configuration.clone(),
+                _ => {
+                    let configuration = Arc::new(self.configuration.read().snapshot());
+                    *snapshot = Some((id, generation, configuration.clone()));
+                    configuration
+                }
+            }
+        });
+        f(&snapshot)
     }
 }
 
 impl Log for LoggerImpl {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        self.configuration.read().filter.enabled(metadata)
+        if !crate::enabled() {
+            return false;
+        }
+        self.with_configuration(|configuration| {
+            configuration.target_allowed(metadata.target()) && configuration.filter.enabled(metadata)
+        })
     }
 
     fn log(&self, record: &log::Record) {
-        let configuration = self.configuration.read();
-
-        if !configuration.filter.matches(record) {
+        if !crate::enabled() {
             return;
         }
+        self.with_configuration(|configuration| log_with_configuration(configuration, record, None));
+        report_pending_errors();
+    }
 
-        let args = record.args().to_string();
-        let message = if let Some(module_path) = record.module_path() {
-            if configuration.prepend_module {
-                [module_path, &args].join(": ")
-            } else {
-                args
+    fn flush(&self) {
+        flush(&self.configuration).ok();
+    }
+}
+
+/// Spawn the thread flushing the configurations received via the returned
+/// sender.
+fn spawn_flush_worker() -> Option<mpsc::Sender<FlushRequest>> {
+    let (requests, received) = mpsc::channel::<FlushRequest>();
+    ThreadBuilder::new()
+        .name("logd-flush".into())
+        .spawn(move || {
+            for (configuration, done) in received {
+                done.send(flush(&configuration).is_ok()).ok();
             }
+        })
+        .ok()?;
+    Some(requests)
+}
+
+/// Flush the output of the logger.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn flush(configuration: &RwLock<Configuration>) -> io::Result<()> {
+    let configuration = configuration.read();
+    flush_repeats(&configuration);
+    flush_rate_limits(&configuration);
+    drop(configuration);
+    report_pending_errors();
+    crate::flush_target()
+}
+
+/// Flush the output of the logger.
+#[cfg(target_os = "android")]
+pub(crate) fn flush(configuration: &RwLock<Configuration>) -> io::Result<()> {
+    let pstore = {
+        let configuration = configuration.read();
+        flush_repeats(&configuration);
+        flush_rate_limits(&configuration);
+        configuration.pstore
+    };
+    report_pending_errors();
+    if pstore {
+        crate::pmsg::flush()?;
+    }
+    crate::flush_target()
+}
+
+/// Write the unreported repetitions of the last record.
+fn flush_repeats(configuration: &Configuration) {
+    if let Some(repeated) = configuration.repeats.as_ref().and_then(|repeats| repeats.take()) {
+        log_repeated(configuration, &repeated);
+    }
+}
+
+/// Write the records dropped by the rate limiter that are not reported yet.
+fn flush_rate_limits(configuration: &Configuration) {
+    if let Some(rate_limiter) = &configuration.rate_limiter {
+        for (tag, suppressed) in rate_limiter.take_suppressed() {
+            log_suppressed(configuration, configuration.buffer_id, &tag, suppressed);
+        }
+    }
+}
+
+/// Write a record reporting the number of records dropped by the rate limiter.
+fn log_suppressed(configuration: &Configuration, buffer_id: Buffer, tag: &str, suppressed: u32) {
+    let message = suppressed_message(suppressed);
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id,
+        tag,
+        priority: Priority::Warn,
+        message: &message,
+        uid: None,
+    };
+    if let Err(e) = crate::log_record(&record, &configuration.timestamp_format) {
+        report_error(configuration, &record, e);
+    }
+}
+
+/// Write a record reporting the repetitions of a record.
+fn log_repeated(configuration: &Configuration, repeated: &Repeated) {
+    let message = repeated.message();
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id: repeated.buffer_id,
+        tag: &repeated.tag,
+        priority: repeated.priority,
+        message: &message,
+        uid: None,
+    };
+    if let Err(e) = crate::log_record(&record, &configuration.timestamp_format) {
+        report_error(configuration, &record, e);
+    }
+}
+
+/// Filter, encode and write a record with the given configuration.
+fn log_with_configuration(configuration: &Configuration, record: &log::Record, uid: Option<u32>) {
+    if !crate::writable() {
+        return;
+    }
+
+    if !configuration.target_allowed(record.target()) || !configuration.filter.matches(record) {
+        return;
+    }
+
+    let message = render_message(
+        record,
+        configuration.format.as_ref(),
+        configuration.prepend_module,
+        configuration.strip_bom,
+    );
+
+    #[cfg(feature = "kv")]
+    let message = match configuration.render_kv.then(|| render_kv(record)).flatten() {
+        Some(kvs) => format!("{} [{}]", message, kvs),
+        None => message,
+    };
+
+    let message = if configuration.strip_ansi && message.contains(ESC) {
+        strip_ansi(&message)
+    } else {
+        message
+    };
+
+    let message = match configuration.context_format.and_then(|format| format.render(&message)) {
+        Some(rendered) => rendered,
+        None => message,
+    };
+
+    let level = record.level();
+    #[cfg(feature = "kv")]
+    let kv_event = configuration
+        .kv_event
+        .and_then(|tag| kv_event_value(record).map(|value| (tag, value)));
+    #[cfg(feature = "kv")]
+    let ts_field = if configuration.honor_ts_field {
+        ts_field(record)
+    } else {
+        None
+    };
+    let priority: Priority = record.metadata().level().into();
+    let tag = match &configuration.tag {
+        TagMode::Target => record.target(),
+        TagMode::TargetStrip => record
+            .target()
+            .split_once("::")
+            .map(|(tag, _)| tag)
+            .unwrap_or_else(|| record.target()),
+        TagMode::Custom(tag) => tag.as_str(),
+    };
+
+    let (tag, message) = match (configuration.version, configuration.version_position) {
+        (Some(version), VersionPosition::Tag) => (Cow::Owned(append_version_to_tag(tag, version)), message),
+        (Some(version), VersionPosition::Message) => (Cow::Borrowed(tag), format!("{} [{}]", message, version)),
+        (None, _) => (Cow::Borrowed(tag), message),
+    };
+    let tag = tag.as_ref();
+
+    let suppressed = match &configuration.rate_limiter {
+        Some(rate_limiter) => match rate_limiter.admit(tag, Instant::now()) {
+            Some(suppressed) => suppressed,
+            None => return,
+        },
+        None => 0,
+    };
+
+    let message = if configuration.embed_priority {
+        embed_priority(priority, tag, &message)
+    } else {
+        message
+    };
+
+    #[cfg(feature = "kv")]
+    let timestamp = ts_field.unwrap_or_else(SystemTime::now);
+    #[cfg(not(feature = "kv"))]
+    let timestamp = SystemTime::now();
+    let record = Record {
+        timestamp,
+        pid: process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id: routed_buffer(&configuration.level_routes, level, configuration.buffer_id),
+        tag,
+        priority,
+        message: &message,
+        uid,
+    };
+
+    if suppressed > 0 {
+        log_suppressed(configuration, record.buffer_id, tag, suppressed);
+    }
+
+    if let Some(repeats) = &configuration.repeats {
+        match repeats.push(&record) {
+            Push::Repeated => return,
+            Push::New(Some(repeated)) => log_repeated(configuration, &repeated),
+            Push::New(None) => (),
+        }
+    }
+
+    if let Err(e) = crate::log_record(&record, &configuration.timestamp_format) {
+        report_error(configuration, &record, e);
+    }
+
+    let copies = copy_buffers(
+        &configuration.level_routes,
+        &configuration.mirror_buffers,
+        level,
+        record.buffer_id,
+    );
+    for buffer_id in copies {
+        let copy = Record { buffer_id, ..record };
+        if let Err(e) = crate::log_record(&copy, &configuration.timestamp_format) {
+            report_error(configuration, &copy, e);
+        }
+    }
+
+    if let Some(ring_buffer) = &configuration.ring_buffer {
+        ring_buffer.push(&record);
+    }
+
+    if configuration.pstore && level <= configuration.pstore_min_level {
+        #[cfg(target_os = "android")]
+        let result = crate::pmsg::log(&record, configuration);
+        #[cfg(not(target_os = "android"))]
+        let result = if crate::dry_run() {
+            crate::pmsg::validate_record(&record)
         } else {
-            args
+            Ok(())
         };
+        if let Err(e) = result {
+            report_error(configuration, &record, e);
+        }
+    }
 
-        let priority: Priority = record.metadata().level().into();
-        let tag = match &configuration.tag {
-            TagMode::Target => record.target(),
-            TagMode::TargetStrip => record
-                .target()
-                .split_once("::")
-                .map(|(tag, _)| tag)
-                .unwrap_or_else(|| record.target()),
-            TagMode::Custom(tag) => tag.as_str(),
-        };
+    #[cfg(all(target_os = "android", feature = "atrace"))]
+    if configuration.atrace {
+        if let Err(e) = crate::atrace::log(&record) {
+            report_error(configuration, &record, e);
+        }
+    }
 
-        let timestamp = SystemTime::now();
-        let record = Record {
-            timestamp,
-            pid: process::id() as u16,
-            thread_id: thread::id() as u16,
-            buffer_id: configuration.buffer_id,
-            tag,
-            priority,
-            message: &message,
-        };
+    #[cfg(feature = "kv")]
+    if let Some((tag, value)) = kv_event {
+        let event = crate::Event { timestamp, tag, value };
+        if let Err(e) = crate::write_event_buffer(Buffer::Events, &event) {
+            report_error(configuration, &record, e);
+        }
+    }
+}
 
-        crate::log_record(&record).ok();
+/// Returns the buffer of records of `level`. The last route of `level`
+/// replacing the buffer of the logger wins.
+fn routed_buffer(routes: &[LevelRoute], level: Level, default: Buffer) -> Buffer {
+    routes
+        .iter()
+        .rev()
+        .find(|route| route.level == level && !route.additional)
+        .map(|route| route.buffer)
+        .unwrap_or(default)
+}
 
-        #[cfg(target_os = "android")]
-        {
-            if configuration.pstore {
-                crate::pmsg::log(&record);
-            }
+/// Returns the buffers records of `level` are additionally written to.
+fn additional_buffers(routes: &[LevelRoute], level: Level) -> impl Iterator<Item = Buffer> + '_ {
+    routes
+        .iter()
+        .filter(move |route| route.level == level && route.additional)
+        .map(|route| route.buffer)
+}
+
+/// Returns the buffers a record of `level` written to `primary` is copied
+/// to. Each buffer is returned once.
+fn copy_buffers(routes: &[LevelRoute], mirrors: &[Buffer], level: Level, primary: Buffer) -> Vec<Buffer> {
+    let mut buffers = Vec::new();
+    for buffer in additional_buffers(routes, level).chain(mirrors.iter().copied()) {
+        if buffer != primary && !buffers.contains(&buffer) {
+            buffers.push(buffer);
         }
     }
+    buffers
+}
 
-    #[cfg(not(target_os = "android"))]
-    fn flush(&self) {
-        use std::io::Write;
-        io::stderr().flush().ok();
+/// Render the message of `record` with `format` or the arguments optionally
+/// prefixed with the module path.
+fn render_message(record: &log::Record, format: Option<&Formatter>, prepend_module: bool, strip_bom: bool) -> String {
+    let mut args = match format {
+        Some(format) => format(record),
+        None => record.args().to_string(),
+    };
+    if strip_bom && args.starts_with(BOM) {
+        args.drain(..BOM.len_utf8());
+    }
+    match record.module_path() {
+        Some(module_path) if prepend_module && format.is_none() => [module_path, &args].join(": "),
+        _ => args,
+    }
+}
+
+/// Returns true and counts the record as dropped if called from the error
+/// handler on this thread.
+pub(crate) fn dropped_by_error_handler() -> bool {
+    let dropped = IN_ERROR_HANDLER.with(Cell::get);
+    if dropped {
+        DROPPED_IN_ERROR_HANDLER.fetch_add(1, Ordering::Relaxed);
+    }
+    dropped
+}
+
+/// Write the shutdown record with the configured tag and buffer.
+fn log_shutdown(configuration: &Configuration) {
+    let tag = match &configuration.tag {
+        TagMode::Custom(tag) => tag.as_str(),
+        TagMode::Target | TagMode::TargetStrip => env!("CARGO_CRATE_NAME"),
+    };
+    let record = Record {
+        timestamp: SystemTime::now(),
+        pid: process::id(),
+        thread_id: thread::id() as u16,
+        buffer_id: configuration.buffer_id,
+        tag,
+        priority: Priority::Info,
+        message: SHUTDOWN_MESSAGE,
+        uid: None,
+    };
+
+    if let Err(e) = crate::log_record(&record, &configuration.timestamp_format) {
+        report_error(configuration, &record, e);
     }
 
     #[cfg(target_os = "android")]
-    fn flush(&self) {
-        if self.configuration.read().pstore {
-            crate::pmsg::flush().ok();
+    if configuration.pstore {
+        if let Err(e) = crate::pmsg::log(&record, configuration) {
+            report_error(configuration, &record, e);
+        }
+    }
+}
+
+/// Report an error of writing `record`. Without an error handler the error is
+/// printed to stderr. Otherwise the error is queued and passed to the handler
+/// by [`report_pending_errors`] once the configuration is unlocked.
+fn report_error(configuration: &Configuration, record: &Record, error: Error) {
+    match &configuration.on_error {
+        Some(on_error) => PENDING_ERRORS.with(|pending| pending.borrow_mut().push((on_error.clone(), error))),
+        None => crate::print_failed(record, &configuration.timestamp_format, &error),
+    }
+}
+
+/// Call the error handler with the errors queued by [`report_error`] on this
+/// thread. Must not be called with the configuration locked.
+fn report_pending_errors() {
+    let pending = PENDING_ERRORS.with(|pending| std::mem::take(&mut *pending.borrow_mut()));
+    for (on_error, error) in pending {
+        handle_error(&on_error, &error);
+    }
+}
+
+/// Call `on_error` with `error`. Records logged by `on_error` on this thread
+/// are dropped.
+fn handle_error(on_error: &ErrorHandler, error: &Error) {
+    /// Clears the flag on drop, also if `on_error` panics.
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IN_ERROR_HANDLER.with(|flag| flag.set(false));
+        }
+    }
+
+    IN_ERROR_HANDLER.with(|flag| flag.set(true));
+    let _reset = Reset;
+    on_error(error);
+}
+
+/// Returns the timestamp of the `_ts` key-value pair of `record`. The value is
+/// the time since the Unix epoch in nanoseconds.
+#[cfg(feature = "kv")]
+fn ts_field(record: &log::Record) -> Option<SystemTime> {
+    let value = record.key_values().get(log::kv::Key::from_str("_ts"))?;
+    let nanos = value.to_u64().or_else(|| value.to_string().parse().ok())?;
+    Some(std::time::UNIX_EPOCH + std::time::Duration::from_nanos(nanos))
+}
+
+/// Collects the key-value pairs of `record` into a list of alternating key
+/// and value strings. Returns `None` if the record has no key-value pairs.
+#[cfg(feature = "kv")]
+fn kv_event_value(record: &log::Record) -> Option<crate::EventValue> {
+    use log::kv::{Error, Key, Value, VisitSource};
+
+    // The number of list elements is encoded in a single byte.
+    const MAX_VALUES: usize = u8::MAX as usize - 1;
+
+    struct Collect(Vec<crate::EventValue>);
+
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+            if self.0.len() < MAX_VALUES {
+                self.0.push(key.as_str().into());
+                self.0.push(value.to_string().as_str().into());
+            }
+            Ok(())
+        }
+    }
+
+    let mut collect = Collect(Vec::new());
+    record.key_values().visit(&mut collect).ok()?;
+    if collect.0.is_empty() {
+        None
+    } else {
+        Some(crate::EventValue::List(collect.0))
+    }
+}
+
+/// Formats the key-value pairs of `record` as space separated `key=value`.
+///
+/// Values with spaces, brackets, quotes or backslashes are quoted and quotes
+/// and backslashes escaped. Returns `None` if the record has no key-values.
+#[cfg(feature = "kv")]
+fn render_kv(record: &log::Record) -> Option<String> {
+    use log::kv::{Error, Key, Value, VisitSource};
+    use std::fmt::Write;
+
+    struct Render(String);
+
+    impl<'kvs> VisitSource<'kvs> for Render {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+            if !self.0.is_empty() {
+                self.0.push(' ');
+            }
+            let value = value.to_string();
+            if value.is_empty() || value.contains(|c: char| c.is_whitespace() || "[]\"\\".contains(c)) {
+                write!(self.0, "{}=\"", key).ok();
+                for c in value.chars() {
+                    if c == '"' || c == '\\' {
+                        self.0.push('\\');
+                    }
+                    self.0.push(c);
+                }
+                self.0.push('"');
+            } else {
+                write!(self.0, "{}={}", key, value).ok();
+            }
+            Ok(())
         }
     }
+
+    let mut render = Render(String::new());
+    record.key_values().visit(&mut render).ok()?;
+    if render.0.is_empty() {
+        None
+    } else {
+        Some(render.0)
+    }
+}
+
+/// Appends `version` to `tag`. The tag is shortened to keep the result within
+/// [`TAG_MAX_LEN`], but not below half of the limit. The version is shortened
+/// if it does not fit after the tag.
+fn append_version_to_tag(tag: &str, version: &str) -> String {
+    let tag_len = TAG_MAX_LEN.saturating_sub(version.len() + 1).max(TAG_MAX_LEN / 2);
+    let tag = truncate_on_char_boundary(tag, tag_len);
+    let version = truncate_on_char_boundary(version, TAG_MAX_LEN - tag.len() - 1);
+    format!("{}@{}", tag, version)
+}
+
+/// Removes ANSI CSI sequences, e.g. color codes, from `message`.
+///
+/// A sequence starts with `ESC [` followed by parameter and intermediate
+/// bytes and ends with a final byte in the range `@` to `~`. Unterminated
+/// sequences are removed up to the end of the message.
+fn strip_ansi(message: &str) -> String {
+    let mut stripped = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find(ESC) {
+        stripped.push_str(&rest[..start]);
+        let sequence = &rest[start + 1..];
+        rest = match sequence.strip_prefix('[') {
+            Some(csi) => match csi.find(|c| ('@'..='~').contains(&c)) {
+                Some(end) => &csi[end + 1..],
+                None => "",
+            },
+            // Not a CSI sequence: keep everything but the escape character.
+            None => sequence,
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Formats `message` in the `brief` format of `logcat`.
+fn embed_priority(priority: Priority, tag: &str, message: &str) -> String {
+    format!("{}/{}: {}", priority, tag, message)
+}
+
+/// Returns true if `target` is this crate or one of its modules.
+fn is_self_target(target: &str) -> bool {
+    const CRATE: &str = env!("CARGO_CRATE_NAME");
+    target
+        .strip_prefix(CRATE)
+        .map(|rest| rest.is_empty() || rest.starts_with("::"))
+        .unwrap_or(false)
+}
+
+#[test]
+fn self_target() {
+    assert!(is_self_target("android_logd_logger"));
+    assert!(is_self_target("android_logd_logger::logger"));
+    assert!(!is_self_target("android_logd_logger_ext"));
+    assert!(!is_self_target("foo::android_logd_logger"));
+}
+
+#[test]
+fn ansi_stripped() {
+    assert_eq!(strip_ansi("\x1b[1;31merror\x1b[0m: failed"), "error: failed");
+    assert_eq!(strip_ansi("plain"), "plain");
+    assert_eq!(strip_ansi("a\x1b[2Kb\x1bc"), "abc");
+    assert_eq!(strip_ansi("unterminated \x1b[1;3"), "unterminated ");
+    assert_eq!(strip_ansi("和\x1b[32m平"), "和平");
+}
+
+#[test]
+fn embed_priority_brief() {
+    assert_eq!(embed_priority(Priority::Info, "MyTag", "msg"), "I/MyTag: msg");
+    assert_eq!(embed_priority(Priority::Verbose, "t", ""), "V/t: ");
+}
+
+#[test]
+fn version_in_tag() {
+    assert_eq!(append_version_to_tag("tag", "1.2.3"), "tag@1.2.3");
+    assert_eq!(
+        append_version_to_tag("a_very_long_tag_name", "1.2.3"),
+        "a_very_long_tag_n@1.2.3"
+    );
+    assert_eq!(
+        append_version_to_tag("tag", "0123456789abcdef0123456789"),
+        "tag@0123456789abcdef012"
+    );
+    assert_eq!(
+        append_version_to_tag("a_very_long_tag_name", "0123456789abcdef0123456789"),
+        "a_very_long@0123456789a"
+    );
+}
+
+#[test]
+#[cfg(feature = "kv")]
+fn kv_values() {
+    use crate::EventValue;
+
+    let kvs = [("user", "alice"), ("attempts", "3")];
+    let record = log::Record::builder().key_values(&kvs).build();
+    assert_eq!(
+        kv_event_value(&record),
+        Some(EventValue::List(vec![
+            "user".into(),
+            "alice".into(),
+            "attempts".into(),
+            "3".into()
+        ]))
+    );
+    assert_eq!(kv_event_value(&log::Record::builder().build()), None);
+}
+
+#[test]
+#[cfg(feature = "kv")]
+fn kv_rendered() {
+    let kvs = [("user", "alice"), ("path", "a b"), ("list", "[1]"), ("quote", "say \"hi\"")];
+    let record = log::Record::builder().key_values(&kvs).build();
+    assert_eq!(
+        render_kv(&record).as_deref(),
+        Some(r#"user=alice path="a b" list="[1]" quote="say \"hi\"""#)
+    );
+
+    assert_eq!(render_kv(&log::Record::builder().build()), None);
+}
+
+#[test]
+#[cfg(feature = "kv")]
+fn ts_field_parsed() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let kvs: [(&str, u64); 1] = [("_ts", 1_500_000_000)];
+    let record = log::Record::builder().key_values(&kvs).build();
+    assert_eq!(ts_field(&record), Some(UNIX_EPOCH + Duration::from_millis(1500)));
+
+    let kvs = [("_ts", "2000000000")];
+    let record = log::Record::builder().key_values(&kvs).build();
+    assert_eq!(ts_field(&record), Some(UNIX_EPOCH + Duration::from_secs(2)));
+
+    let kvs = [("_ts", "invalid")];
+    let record = log::Record::builder().key_values(&kvs).build();
+    assert_eq!(ts_field(&record), None);
+}
+
+#[test]
+fn level_routed() {
+    let routes = [
+        LevelRoute {
+            level: Level::Error,
+            buffer: Buffer::Crash,
+            additional: true,
+        },
+        LevelRoute {
+            level: Level::Warn,
+            buffer: Buffer::System,
+            additional: false,
+        },
+    ];
+    assert_eq!(routed_buffer(&routes, Level::Error, Buffer::Main), Buffer::Main);
+    assert_eq!(additional_buffers(&routes, Level::Error).collect::<Vec<_>>(), [Buffer::Crash]);
+    assert_eq!(routed_buffer(&routes, Level::Warn, Buffer::Main), Buffer::System);
+    assert_eq!(additional_buffers(&routes, Level::Warn).count(), 0);
+    assert_eq!(routed_buffer(&routes, Level::Info, Buffer::Radio), Buffer::Radio);
+}
+
+#[test]
+fn copies_deduplicated() {
+    let buffers = [Buffer::Custom(220), Buffer::Custom(221), Buffer::Custom(222)];
+    let copies = copy_buffers(
+        &[],
+        &[buffers[1], buffers[0], buffers[2], buffers[1]],
+        Level::Info,
+        buffers[0],
+    );
+    assert_eq!(copies, [buffers[1], buffers[2]]);
+}
+
+#[test]
+fn message_rendered() {
+    let record = log::Record::builder()
+        .args(format_args!("\u{feff}msg"))
+        .module_path(Some("module"))
+        .line(Some(7))
+        .build();
+    assert_eq!(render_message(&record, None, false, true), "msg");
+    assert_eq!(render_message(&record, None, true, true), "module: msg");
+
+    let format: Formatter = Arc::new(|record| format!("{} [{}]", record.args(), record.line().unwrap()));
+    assert_eq!(render_message(&record, Some(&format), true, true), "msg [7]");
+}
+
+#[test]
+fn error_handler_reentrancy() {
+    let on_error: ErrorHandler = Arc::new(|_| assert!(dropped_by_error_handler()));
+    let dropped = dropped_in_error_handler();
+    handle_error(&on_error, &Error::RecordSize);
+    assert_eq!(dropped_in_error_handler(), dropped + 1);
+    assert!(!dropped_by_error_handler());
+}
+
+#[test]
+fn batch_unlocked() {
+    let logger = Logger {
+        configuration: Arc::new(RwLock::new(crate::Builder::new().configuration())),
+        pending_level: Default::default(),
+    };
+    logger.batch(|_| {
+        let other = logger.clone();
+        std::thread::spawn(move || {
+            other.prepend_module(true);
+        })
+        .join()
+        .unwrap();
+    });
+    assert!(logger.configuration.read().prepend_module);
+}
+
+#[test]
+fn filter_merged() {
+    let enabled = |logger: &Logger, target: &str, level: Level| {
+        let metadata = Metadata::builder().target(target).level(level).build();
+        logger.configuration.read().filter.enabled(&metadata)
+    };
+
+    let logger = Logger {
+        configuration: Arc::new(RwLock::new(crate::Builder::new().configuration())),
+        pending_level: Default::default(),
+    };
+    logger.filter_level(LevelFilter::Info);
+    logger.filter_module("foo", LevelFilter::Debug);
+    assert!(enabled(&logger, "foo", Level::Debug));
+    assert!(enabled(&logger, "bar", Level::Info));
+    assert!(!enabled(&logger, "bar", Level::Debug));
+
+    logger.filter(None, LevelFilter::Warn);
+    assert!(enabled(&logger, "foo", Level::Debug));
+    assert!(!enabled(&logger, "bar", Level::Info));
+    assert_eq!(logger.configuration.read().filter.filter(), LevelFilter::Debug);
+}
+
+#[test]
+fn flush_without_pstore() {
+    let logger = Logger {
+        configuration: Arc::new(RwLock::new(crate::Builder::new().configuration())),
+        pending_level: Default::default(),
+    };
+    logger.write().pstore = false;
+    assert!(logger.flush());
+
+    logger.write().flush_timeout = Some(Duration::from_secs(1));
+    assert!(logger.flush());
+    assert!(logger.flush());
+}
+
+#[test]
+fn getters() {
+    let mut builder = crate::Builder::new();
+    builder.buffer(Buffer::Crash).tag("foo").filter_level(LevelFilter::Warn);
+    let logger = Logger {
+        configuration: Arc::new(RwLock::new(builder.configuration())),
+        pending_level: Default::default(),
+    };
+    assert_eq!(logger.level(), LevelFilter::Warn);
+    assert_eq!(logger.buffer_id(), Buffer::Crash);
+    assert_eq!(logger.tag_mode(), TagModeDescriptor::Custom("foo".into()));
+    assert!(logger.pstore_enabled());
+
+    logger
+        .buffer(Buffer::Radio)
+        .tag_target()
+        .filter_module("bar", LevelFilter::Trace);
+    logger.write().pstore = false;
+    assert_eq!(logger.level(), LevelFilter::Trace);
+    assert_eq!(logger.buffer_id(), Buffer::Radio);
+    assert_eq!(logger.tag_mode(), TagModeDescriptor::Target);
+    assert!(!logger.pstore_enabled());
+}
+
+#[test]
+fn reload_from_env() {
+    const VAR: &str = "ANDROID_LOGD_LOGGER_TEST_RELOAD_FROM_ENV";
+
+    let logger = Logger {
+        configuration: Arc::new(RwLock::new(crate::Builder::new().configuration())),
+        pending_level: Default::default(),
+    };
+    logger.filter_level(LevelFilter::Warn);
+    assert!(!logger.reload_from_env(VAR));
+    assert_eq!(logger.level(), LevelFilter::Warn);
+
+    std::env::set_var(VAR, "error,foo=trace");
+    assert!(logger.reload_from_env(VAR));
+    assert_eq!(logger.level(), LevelFilter::Trace);
+    assert_eq!(logger.module_levels(), vec![("foo".to_string(), LevelFilter::Trace)]);
+    std::env::remove_var(VAR);
+}
+
+#[test]
+fn filter_level_debounced() {
+    let logger = Logger {
+        configuration: Arc::new(RwLock::new(crate::Builder::new().configuration())),
+        pending_level: Default::default(),
+    };
+    logger.filter_level_debounced(LevelFilter::Trace, Duration::from_millis(10));
+    logger.filter_level_debounced(LevelFilter::Warn, Duration::from_millis(10));
+    assert!(logger.pending_level.lock().is_some());
+
+    // Wait for the debounce thread to apply the last level.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while logger.pending_level.lock().is_some() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(logger.pending_level.lock().is_none());
+    assert_eq!(logger.configuration.read().filter.filter(), LevelFilter::Warn);
 }
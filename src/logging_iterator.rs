@@ -1,19 +1,35 @@
+use std::{borrow::Cow, iter};
+
 /// Chunk iterator over a string slice scaled to newline characters.
 ///
-/// This iterator will yield string slices below the maximum byte length
+/// This iterator will yield string slices of at most the maximum byte length
 /// specified. If there are newline characters in a chunk, it will split after
-/// the last of them instead of at the maximum length.
+/// the last of them instead of at the maximum length. A `\r\n` line ending
+/// is never split.
 ///
 /// This is following the C implementation of the pmsg writer in Android:
 /// https://cs.android.com/android/platform/superproject/+/master:system/logging/liblog/pmsg_writer.cpp;l=165
-pub(crate) struct NewlineScaledChunkIterator<'a> {
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::chunk::NewlineScaledChunkIterator;
+///
+/// let chunks = NewlineScaledChunkIterator::new("first\nsecond line", 12).collect::<Vec<_>>();
+/// assert_eq!(chunks, ["first\n", "second line"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct NewlineScaledChunkIterator<'a> {
     data: &'a str,
     max_byte_length: usize,
 }
 
 impl<'a> NewlineScaledChunkIterator<'a> {
-    /// Create a new iterator instance.
-    #[allow(dead_code)]
+    /// Create a new iterator over `data` yielding chunks of at most
+    /// `max_byte_length` bytes.
+    ///
+    /// Characters are never split: a character longer than
+    /// `max_byte_length` is yielded on its own.
     pub fn new(data: &'a str, max_byte_length: usize) -> Self {
         Self { data, max_byte_length }
     }
@@ -25,20 +41,28 @@ impl<'a> Iterator for NewlineScaledChunkIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         // We yield all or split depending on the byte-length,
         // *not* the character length.
-        match self.data.as_bytes().len() {
+        match self.data.len() {
             0 => None,
-            x if x < self.max_byte_length => {
+            x if x <= self.max_byte_length => {
                 let last_piece = self.data;
                 self.data = "";
                 Some(last_piece)
             }
             _length_above_limit => {
                 // Find char boundary before the max length
-                let split_idx = find_char_boundary_before_idx(self.data, self.max_byte_length);
+                let split_idx = truncate_on_char_boundary(self.data, self.max_byte_length).len();
 
                 // Try to find a newline char before the split point
                 let split_idx = match self.data[..split_idx].rfind('\n') {
                     Some(byte_idx) => byte_idx + 1, // *After* the newline
+                    None if split_idx == 0 => self.data.chars().next().map_or(0, char::len_utf8),
+                    // Keep a \r\n line ending in the next chunk
+                    None if split_idx > 1
+                        && self.data[..split_idx].ends_with('\r')
+                        && self.data[split_idx..].starts_with('\n') =>
+                    {
+                        split_idx - 1
+                    }
                     None => split_idx,
                 };
 
@@ -52,15 +76,37 @@ impl<'a> Iterator for NewlineScaledChunkIterator<'a> {
     }
 }
 
-/// Find the character boundary before an index in a string slice.
-fn find_char_boundary_before_idx(data: &str, mut idx: usize) -> usize {
-    loop {
-        if data.is_char_boundary(idx) || idx == 0 {
-            return idx;
+/// Limit `chunks` to at most `max` chunks. If chunks are dropped, the last
+/// chunk is replaced by a marker with the number of dropped chunks.
+pub(crate) fn limit_chunks<'a>(chunks: impl Iterator<Item = &'a str>, max: usize) -> impl Iterator<Item = Cow<'a, str>> {
+    let mut chunks = chunks.peekable();
+    let mut remaining = max;
+    iter::from_fn(move || {
+        if remaining == 0 {
+            return None;
+        }
+        let chunk = chunks.next()?;
+        remaining -= 1;
+        if remaining == 0 && chunks.peek().is_some() {
+            let dropped = 1 + chunks.by_ref().count();
+            Some(Cow::Owned(format!("[truncated, {} more chunks dropped]", dropped)))
+        } else {
+            Some(Cow::Borrowed(chunk))
         }
+    })
+}
+
+/// Truncate a string slice to at most `max` bytes without splitting a character.
+pub(crate) fn truncate_on_char_boundary(data: &str, max: usize) -> &str {
+    if max >= data.len() {
+        return data;
+    }
 
+    let mut idx = max;
+    while !data.is_char_boundary(idx) {
         idx -= 1;
     }
+    &data[..idx]
 }
 
 #[cfg(test)]
@@ -68,16 +114,88 @@ mod test {
     use super::*;
 
     #[test]
-    fn test_find_char_boundary() {
+    fn test_truncate_on_char_boundary() {
         let test_str = "World 和 is what we want";
         let idx = 7; // inside the Chinese 'peace' sign
 
-        let char_boundary_before = find_char_boundary_before_idx(test_str, idx);
-        assert_eq!(char_boundary_before, 6);
+        let truncated = truncate_on_char_boundary(test_str, idx);
+        assert_eq!(truncated, "World ");
+        assert_eq!(&test_str[truncated.len()..], "和 is what we want");
+
+        assert_eq!(truncate_on_char_boundary(test_str, 0), "");
+        assert_eq!(truncate_on_char_boundary(test_str, 1000), test_str);
+    }
+
+    #[test]
+    fn test_truncate_never_splits_chars() {
+        let test_str = "a和ü😀b";
+        for max in 0..=test_str.len() + 1 {
+            let truncated = truncate_on_char_boundary(test_str, max);
+            assert!(truncated.len() <= max);
+            assert!(test_str.is_char_boundary(truncated.len()));
+        }
+    }
+
+    #[test]
+    fn test_chunks_are_complete_characters() {
+        let test_str = "和平😀\n".repeat(100);
+        for max in 4..64 {
+            let chunks: Vec<&str> = NewlineScaledChunkIterator::new(&test_str, max).collect();
+            assert!(chunks.iter().all(|chunk| chunk.len() <= max));
+            assert_eq!(chunks.concat(), test_str);
+        }
+    }
+
+    #[test]
+    fn test_chunks_at_max_length() {
+        let test_str = "line\n".repeat(10);
+        let max = test_str.len();
+        assert_eq!(
+            NewlineScaledChunkIterator::new(&test_str, max).collect::<Vec<_>>(),
+            [&test_str]
+        );
+
+        let test_str = &test_str[..max - 1];
+        assert_eq!(NewlineScaledChunkIterator::new(test_str, max).collect::<Vec<_>>(), [test_str]);
+
+        let test_str = "line\n".repeat(10) + "a";
+        assert_eq!(
+            NewlineScaledChunkIterator::new(&test_str, max).collect::<Vec<_>>(),
+            [&test_str[..max], "a"]
+        );
+    }
+
+    #[test]
+    fn test_chunks_below_char_length() {
+        let chunks: Vec<&str> = NewlineScaledChunkIterator::new("和a", 2).collect();
+        assert_eq!(chunks, ["和", "a"]);
+        let chunks: Vec<&str> = NewlineScaledChunkIterator::new("ab", 0).collect();
+        assert_eq!(chunks, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_crlf_kept_together() {
+        let mut nl_iter = NewlineScaledChunkIterator::new("abcdefghij\r\nkl", 11);
+        assert_eq!(nl_iter.next(), Some("abcdefghij"));
+        assert_eq!(nl_iter.next(), Some("\r\nkl"));
+        assert_eq!(nl_iter.next(), None);
+
+        let test_str = "first\r\nsecond\nthird line\r\nfourth\r\n".repeat(10);
+        for max in 2..64 {
+            let chunks: Vec<&str> = NewlineScaledChunkIterator::new(&test_str, max).collect();
+            assert!(chunks.iter().all(|chunk| !chunk.ends_with('\r')));
+            assert_eq!(chunks.concat(), test_str);
+        }
+    }
+
+    #[test]
+    fn test_limit_chunks() {
+        let chunks = ["a", "b", "c", "d"];
+        let limited: Vec<_> = limit_chunks(chunks.iter().copied(), 2).collect();
+        assert_eq!(limited, ["a", "[truncated, 3 more chunks dropped]"]);
 
-        let (first_part, second_part) = test_str.split_at(char_boundary_before);
-        assert_eq!(first_part, "World ");
-        assert_eq!(second_part, "和 is what we want");
+        let limited: Vec<_> = limit_chunks(chunks.iter().copied(), 4).collect();
+        assert_eq!(limited, chunks);
     }
 
     #[test]
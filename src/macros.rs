@@ -0,0 +1,111 @@
+/// Logs a message to an explicit buffer.
+///
+/// The level is a [`log::Level`] or a [`Priority`](crate::Priority). The
+/// message is written with [`log`](crate::log) and bypasses the buffer and
+/// filter configuration of the global logger. The tag is the root of the
+/// calling module path.
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::{logd_log, Buffer};
+/// use log::Level;
+///
+/// logd_log!(Buffer::Crash, Level::Error, "something went wrong: {}", 42);
+/// ```
+#[macro_export]
+macro_rules! logd_log {
+    ($buffer:expr, $level:expr, $($arg:tt)+) => {
+        $crate::__private_log($buffer, $level, ::std::module_path!(), ::std::format_args!($($arg)+))
+    };
+}
+
+/// Logs a message at the error level to an explicit buffer.
+///
+/// See [`logd_log!`].
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::{logd_error, Buffer};
+///
+/// logd_error!(Buffer::Crash, "something went wrong: {}", 42);
+/// ```
+#[macro_export]
+macro_rules! logd_error {
+    ($buffer:expr, $($arg:tt)+) => {
+        $crate::logd_log!($buffer, $crate::Priority::Error, $($arg)+)
+    };
+}
+
+/// Logs a message at the warn level to an explicit buffer.
+///
+/// See [`logd_log!`].
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::{logd_warn, Buffer};
+///
+/// logd_warn!(Buffer::System, "low memory");
+/// ```
+#[macro_export]
+macro_rules! logd_warn {
+    ($buffer:expr, $($arg:tt)+) => {
+        $crate::logd_log!($buffer, $crate::Priority::Warn, $($arg)+)
+    };
+}
+
+/// Logs a message at the info level to an explicit buffer.
+///
+/// See [`logd_log!`].
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::{logd_info, Buffer};
+///
+/// logd_info!(Buffer::Radio, "signal strength: {}", -70);
+/// ```
+#[macro_export]
+macro_rules! logd_info {
+    ($buffer:expr, $($arg:tt)+) => {
+        $crate::logd_log!($buffer, $crate::Priority::Info, $($arg)+)
+    };
+}
+
+/// Logs a message at the debug level to an explicit buffer.
+///
+/// See [`logd_log!`].
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::{logd_debug, Buffer};
+///
+/// logd_debug!(Buffer::System, "state: {}", "idle");
+/// ```
+#[macro_export]
+macro_rules! logd_debug {
+    ($buffer:expr, $($arg:tt)+) => {
+        $crate::logd_log!($buffer, $crate::Priority::Debug, $($arg)+)
+    };
+}
+
+/// Logs a message at the trace level to an explicit buffer.
+///
+/// See [`logd_log!`].
+///
+/// # Examples
+///
+/// ```
+/// use android_logd_logger::{logd_trace, Buffer};
+///
+/// logd_trace!(Buffer::System, "entering {}", "main");
+/// ```
+#[macro_export]
+macro_rules! logd_trace {
+    ($buffer:expr, $($arg:tt)+) => {
+        $crate::logd_log!($buffer, $crate::Priority::Verbose, $($arg)+)
+    };
+}
@@ -0,0 +1,121 @@
+use crate::{thread, Buffer, Priority};
+use parking_lot::Mutex;
+use std::{
+    backtrace::Backtrace,
+    panic::{self, PanicHookInfo},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+/// Tag of panic records.
+const TAG: &str = "panic";
+
+/// Panic hook.
+type Hook = dyn Fn(&PanicHookInfo<'_>) + Send + Sync + 'static;
+
+/// Hook replaced by [`install_panic_hook`]. `None` if not installed.
+static PREVIOUS: Mutex<Option<Arc<Hook>>> = parking_lot::const_mutex(None);
+
+/// Append a backtrace to panic records.
+static BACKTRACE: AtomicBool = AtomicBool::new(false);
+
+/// Install a panic hook writing panics to [`Buffer::Crash`].
+///
+/// The record contains the thread, the location and the message of the panic
+/// and is written with [`Priority::Error`] and the tag `panic` via
+/// [`log`](crate::log). The previously set hook is called afterwards.
+/// Installing the hook again has no effect besides updating the backtrace
+/// option.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::install_panic_hook();
+/// ```
+pub fn install_panic_hook() {
+    install(false);
+}
+
+/// Install a panic hook like [`install_panic_hook`] and append a backtrace to
+/// the record.
+///
+/// The backtrace is captured regardless of `RUST_BACKTRACE`.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::install_panic_hook_with_backtrace();
+/// ```
+pub fn install_panic_hook_with_backtrace() {
+    install(true);
+}
+
+/// Restore the panic hook replaced by [`install_panic_hook`].
+///
+/// A hook set after the installation is replaced as well.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::install_panic_hook();
+/// android_logd_logger::uninstall_panic_hook();
+/// ```
+pub fn uninstall_panic_hook() {
+    let mut previous = PREVIOUS.lock();
+    if let Some(previous) = previous.take() {
+        panic::set_hook(Box::new(move |info| previous(info)));
+    }
+}
+
+fn install(backtrace: bool) {
+    BACKTRACE.store(backtrace, Ordering::Relaxed);
+    let mut previous = PREVIOUS.lock();
+    if previous.is_none() {
+        *previous = Some(Arc::from(panic::take_hook()));
+        panic::set_hook(Box::new(hook));
+    }
+}
+
+fn hook(info: &PanicHookInfo<'_>) {
+    let mut message = message(info);
+    if BACKTRACE.load(Ordering::Relaxed) {
+        message.push('\n');
+        message.push_str(&Backtrace::force_capture().to_string());
+    }
+    crate::log(
+        SystemTime::now(),
+        Buffer::Crash,
+        Priority::Error,
+        process::id(),
+        thread::id() as u16,
+        TAG,
+        &message,
+    )
+    .ok();
+
+    // Release the lock before calling the previous hook which may panic.
+    let previous = PREVIOUS.lock().clone();
+    if let Some(previous) = previous {
+        previous(info);
+    }
+}
+
+/// Format a panic like the default hook of the standard library.
+fn message(info: &PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    let payload = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    let current = std::thread::current();
+    let name = current.name().unwrap_or("<unnamed>");
+    match info.location() {
+        Some(location) => format!("thread '{}' panicked at {}:\n{}", name, location, payload),
+        None => format!("thread '{}' panicked:\n{}", name, payload),
+    }
+}
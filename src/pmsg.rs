@@ -1,12 +1,21 @@
-use crate::{logging_iterator::NewlineScaledChunkIterator, Buffer, Priority, Record};
-use bytes::{BufMut, BytesMut};
-use std::{
-    fs::{File, OpenOptions},
-    io::{self, Write},
-    time::UNIX_EPOCH,
+use crate::{
+    logging_iterator::{limit_chunks, NewlineScaledChunkIterator},
+    Buffer, ByteOrder, Error, Priority, Record,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::time::Duration;
+#[cfg(target_os = "android")]
+use {
+    crate::{logger::Configuration, ClockSource},
+    std::{
+        fs::{File, OpenOptions},
+        io::{self, Write},
+        time::UNIX_EPOCH,
+    },
 };
 
 /// Persistent message charater device
+#[cfg(target_os = "android")]
 const PMSG0: &str = "/dev/pmsg0";
 
 /// 'Magic' marker value of android logger
@@ -17,99 +26,390 @@ const ANDROID_LOG_ENTRY_MAX_PAYLOAD: usize = 4068;
 const ANDROID_LOG_PMSG_SEQUENCE_INCREMENT: usize = 1000;
 // Maximum sequence number in Android logging system
 const ANDROID_LOG_PMSG_MAX_SEQUENCE: usize = 256000;
+/// Length of the pmsg and log headers of a packet
+const HEADER_LEN: usize = 7 + 11;
+
+/// Maximum number of retries of a write or flush interrupted by a signal.
+const MAX_INTERRUPTED_RETRIES: usize = 8;
 
 /// Fixed UID to use. This does not show up in the log output so we save the
 /// system call to determine it.
 const DUMMY_UID: u16 = 0;
 
+#[cfg(target_os = "android")]
 lazy_static::lazy_static! {
     /// Shared file handle to the open pmsg device.
     static ref PMSG_DEV: parking_lot::RwLock<File> = parking_lot::RwLock::new(
-        OpenOptions::new().write(true).open(PMSG0).expect("failed to open pmsg device")
+        open().expect("failed to open pmsg device")
     );
 }
 
+/// Open the pmsg device.
+#[cfg(target_os = "android")]
+fn open() -> io::Result<File> {
+    OpenOptions::new().write(true).open(PMSG0)
+}
+
+/// Replace the pmsg device handle with a newly opened one.
+#[cfg(target_os = "android")]
+pub(crate) fn reopen() -> io::Result<()> {
+    let mut pmsg = PMSG_DEV.write();
+    *pmsg = open()?;
+    Ok(())
+}
+
 /// Send a log message to pmsg0
-pub(crate) fn log(record: &Record) {
-    // Iterate over chunks below the maximum payload byte length, scaled to
-    // the last newline character. This follows the C implementation:
-    // https://cs.android.com/android/platform/superproject/+/master:system/logging/liblog/pmsg_writer.cpp;l=165
-    for (idx, msg_part) in NewlineScaledChunkIterator::new(record.message, ANDROID_LOG_ENTRY_MAX_PAYLOAD).enumerate() {
-        let sequence_nr = idx * ANDROID_LOG_PMSG_SEQUENCE_INCREMENT;
-        if sequence_nr >= ANDROID_LOG_PMSG_MAX_SEQUENCE {
-            return;
+///
+/// In dry run mode the packets are encoded and validated but not written.
+#[cfg(target_os = "android")]
+pub(crate) fn log(record: &Record, configuration: &Configuration) -> Result<(), Error> {
+    let timestamp = match configuration.pmsg_clock {
+        ClockSource::Realtime => record.timestamp.duration_since(UNIX_EPOCH).unwrap(),
+        ClockSource::Boottime => boottime(),
+    };
+    #[cfg(feature = "pmsg-compress")]
+    let compress = configuration.pmsg_compress;
+    #[cfg(not(feature = "pmsg-compress"))]
+    let compress = false;
+
+    for packet in packets(record, timestamp, compress) {
+        if crate::dry_run() {
+            validate(&packet)?;
+            continue;
         }
+        write(record, &packet);
+    }
+    Ok(())
+}
+
+/// Encode and validate a log message without writing it.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn validate_record(record: &Record) -> Result<(), Error> {
+    packets(record, Duration::ZERO, false)
+        .iter()
+        .try_for_each(|packet| validate(packet))
+}
 
-        log_pmsg_packet(record, msg_part);
+/// Check that the payload of a packet does not exceed the pmsg limit.
+fn validate(packet: &[u8]) -> Result<(), Error> {
+    if packet.len() > HEADER_LEN + ANDROID_LOG_ENTRY_MAX_PAYLOAD {
+        Err(Error::RecordSize)
+    } else {
+        Ok(())
     }
 }
 
+/// Build the pmsg packets of a record with the given timestamp.
+///
+/// Iterate over chunks of the message scaled to the last newline character.
+/// The payload of a packet, i.e. priority, tag and message chunk, is at most
+/// the maximum payload byte length. This follows the C implementation:
+/// https://cs.android.com/android/platform/superproject/+/master:system/logging/liblog/pmsg_writer.cpp;l=165
+/// The sequence number of the chunks is limited.
+#[cfg_attr(not(feature = "pmsg-compress"), allow(unused_variables))]
+pub(crate) fn packets(record: &Record, timestamp: Duration, compress: bool) -> Vec<Bytes> {
+    let max_chunks = crate::max_chunks().min(ANDROID_LOG_PMSG_MAX_SEQUENCE / ANDROID_LOG_PMSG_SEQUENCE_INCREMENT);
+    let chunks = NewlineScaledChunkIterator::new(record.message, max_message_len(record.tag));
+    limit_chunks(chunks, max_chunks)
+        .map(|msg_part| {
+            #[cfg(feature = "pmsg-compress")]
+            if compress {
+                if let Some(compressed) = crate::compress::compress(msg_part.as_bytes()) {
+                    return packet(record, timestamp, &compressed).freeze();
+                }
+            }
+
+            packet(record, timestamp, msg_part.as_bytes()).freeze()
+        })
+        .collect()
+}
+
+/// Maximum length of a message chunk with `tag`. The payload contains the
+/// priority, the tag and the chunk, each string zero terminated. At least
+/// one character fits.
+fn max_message_len(tag: &str) -> usize {
+    ANDROID_LOG_ENTRY_MAX_PAYLOAD.saturating_sub(1 + tag.len() + 1 + 1).max(4)
+}
+
 /// Flush the pmsg writer.
+#[cfg(target_os = "android")]
 pub(crate) fn flush() -> io::Result<()> {
     let mut pmsg = PMSG_DEV.write();
-    pmsg.flush()
+    retry_interrupted(|| pmsg.flush())
+}
+
+/// Call `f` again if it fails with `ErrorKind::Interrupted`. The number of
+/// retries is bounded to avoid spinning on persistent interruptions.
+fn retry_interrupted<T>(mut f: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    let mut retries = 0;
+    loop {
+        match f() {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted && retries < MAX_INTERRUPTED_RETRIES => retries += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Write the whole `packet` to `pmsg`. Interrupted writes are retried a
+/// limited number of times. `written` is advanced by the number of bytes
+/// written, also if the write fails.
+fn write_packet(pmsg: &mut impl std::io::Write, packet: &[u8], written: &mut usize) -> std::io::Result<()> {
+    while *written < packet.len() {
+        match retry_interrupted(|| pmsg.write(&packet[*written..]))? {
+            0 => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero)),
+            n => *written += n,
+        }
+    }
+    Ok(())
+}
+
+/// Write `packet` to `pmsg`. If nothing was written the device is reopened
+/// with `reopen` and the write is retried once. A packet written in part is
+/// not retried because the retry would duplicate the written part.
+fn write_or_reopen<W: std::io::Write>(
+    pmsg: &mut W,
+    packet: &[u8],
+    reopen: impl FnOnce() -> std::io::Result<W>,
+) -> std::io::Result<()> {
+    let mut written = 0;
+    match write_packet(pmsg, packet, &mut written) {
+        Err(_) if written == 0 => {
+            *pmsg = reopen()?;
+            write_packet(pmsg, packet, &mut written)
+        }
+        result => result,
+    }
+}
+
+/// Time since boot including the time the system was suspended.
+#[cfg(target_os = "android")]
+fn boottime() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // Safety: `ts` is a valid, writable timespec.
+    unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
 }
 
-fn log_pmsg_packet(record: &Record, msg_part: &str) {
+/// Build a pmsg packet of a message part.
+fn packet(record: &Record, timestamp: Duration, msg_part: &[u8]) -> BytesMut {
     const PMSG_HEADER_LEN: u16 = 7;
     const LOG_HEADER_LEN: u16 = 11;
     // The payload is made up by:
     // - 1 byte for the priority
     // - tag bytes + 1 byte zero terminator
     // - message bytes + 1 byte zero terminator
-    let payload_len: u16 = (1 + record.tag.bytes().len() + 1 + msg_part.bytes().len() + 1) as u16;
+    let payload_len: u16 = (1 + record.tag.len() + 1 + msg_part.len() + 1) as u16;
 
     let packet_len = PMSG_HEADER_LEN + LOG_HEADER_LEN + payload_len;
     let mut buffer = bytes::BytesMut::with_capacity(packet_len as usize);
-    let timestamp = record.timestamp.duration_since(UNIX_EPOCH).unwrap();
 
-    write_pmsg_header(&mut buffer, packet_len, DUMMY_UID, record.pid);
+    // The pmsg header holds 16 bits of the UID and PID.
+    let uid = record.uid.map(|uid| uid as u16).unwrap_or(DUMMY_UID);
+    let order = crate::byte_order();
+    write_pmsg_header(&mut buffer, order, packet_len, uid, record.pid as u16);
     write_log_header(
         &mut buffer,
+        order,
         record.buffer_id,
         record.thread_id,
         timestamp.as_secs() as u32,
         timestamp.subsec_nanos(),
     );
     write_payload(&mut buffer, record.priority, record.tag, msg_part);
+    buffer
+}
 
-    {
-        let mut pmsg = PMSG_DEV.write();
-        if let Err(e) = pmsg.write_all(&buffer) {
-            eprintln!("Failed to log message part to pmsg: \"{}: {}\": {}", record.tag, msg_part, e);
-        }
+/// Write a pmsg packet to the device.
+#[cfg(target_os = "android")]
+fn write(record: &Record, packet: &[u8]) {
+    let mut pmsg = PMSG_DEV.write();
+    // The device node might have been recreated.
+    let result = write_or_reopen(&mut *pmsg, packet, open);
+    if let Err(e) = result {
+        eprintln!(
+            "Failed to log message part to pmsg: \"{}: {}\": {}",
+            record.tag, record.message, e
+        );
+        crate::diagnostics::report("pmsg", format_args!("failed to write to pmsg: {}", e));
     }
 }
 
-fn write_pmsg_header(buffer: &mut BytesMut, packet_len: u16, uid: u16, pid: u16) {
+fn write_pmsg_header(buffer: &mut BytesMut, order: ByteOrder, packet_len: u16, uid: u16, pid: u16) {
     // magic logger marker
     // https://cs.android.com/android/platform/superproject/+/master:system/logging/liblog/include/private/android_logger.h;drc=a66c835cf06a1bee5355f8f61bf543d9ab2aa133;bpv=0;bpt=1;l=34
     buffer.put_u8(ANDROID_LOG_MAGIC_CHAR);
     // message length
-    buffer.put_u16_le(packet_len);
-    buffer.put_u16_le(uid);
-    buffer.put_u16_le(pid);
+    order.put_u16(buffer, packet_len);
+    order.put_u16(buffer, uid);
+    order.put_u16(buffer, pid);
 }
 
-fn write_log_header(buffer: &mut BytesMut, buffer_id: Buffer, thread_id: u16, timestamp_secs: u32, timestamp_subsec_nanos: u32) {
+fn write_log_header(
+    buffer: &mut BytesMut,
+    order: ByteOrder,
+    buffer_id: Buffer,
+    thread_id: u16,
+    timestamp_secs: u32,
+    timestamp_subsec_nanos: u32,
+) {
     buffer.put_u8(buffer_id.into());
-    buffer.put_u16_le(thread_id);
-    buffer.put_u32_le(timestamp_secs);
+    order.put_u16(buffer, thread_id);
+    order.put_u32(buffer, timestamp_secs);
     // In the original pmsg writer, the nanoseconds timestamp is hijacked as
     // sequence number:
     // https://cs.android.com/android/platform/superproject/+/master:system/logging/liblog/pmsg_writer.cpp;l=169
     // However this would lead to different timestamps in the `logd` stream and
     // the logs from the `pstore`. We could not find adverse effects from
     // dropping the sequence number and using the real nanoseconds.
-    buffer.put_u32_le(timestamp_subsec_nanos);
+    order.put_u32(buffer, timestamp_subsec_nanos);
 }
 
-fn write_payload(buffer: &mut BytesMut, priority: Priority, tag: &str, msg_part: &str) {
+fn write_payload(buffer: &mut BytesMut, priority: Priority, tag: &str, msg_part: &[u8]) {
     buffer.put_u8(priority as u8);
     // Tag with zero terminator
     buffer.put(tag.as_bytes());
     buffer.put_u8(0);
     // Message part with zero terminator
-    buffer.put(msg_part.as_bytes());
+    buffer.put(msg_part);
     buffer.put_u8(0);
 }
+
+#[test]
+fn packets_chunked() {
+    let message = "a".repeat(max_message_len("tag") + 1);
+    let record = Record {
+        timestamp: std::time::SystemTime::now(),
+        pid: 0x0102,
+        thread_id: 3,
+        buffer_id: Buffer::Main,
+        tag: "tag",
+        priority: Priority::Info,
+        message: &message,
+        uid: None,
+    };
+    let packets = packets(&record, Duration::new(1, 2), false);
+    assert_eq!(packets.len(), 2);
+
+    let packet = &packets[1];
+    assert_eq!(packet[0], ANDROID_LOG_MAGIC_CHAR);
+    assert_eq!(u16::from_le_bytes([packet[1], packet[2]]) as usize, packet.len());
+    assert_eq!(&packet[5..7], &[2, 1]);
+    assert_eq!(&packet[18..], b"\x04tag\0a\0");
+    assert_eq!(packets[0].len() - HEADER_LEN, ANDROID_LOG_ENTRY_MAX_PAYLOAD);
+
+    let message = &message[1..];
+    let record = Record { message, ..record };
+    let single = crate::pmsg::packets(&record, Duration::new(1, 2), false);
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].len() - HEADER_LEN, ANDROID_LOG_ENTRY_MAX_PAYLOAD);
+}
+
+#[test]
+fn packets_validated() {
+    let record = Record {
+        timestamp: std::time::SystemTime::now(),
+        pid: 1,
+        thread_id: 3,
+        buffer_id: Buffer::Main,
+        tag: "tag",
+        priority: Priority::Info,
+        message: &"a".repeat(2 * ANDROID_LOG_ENTRY_MAX_PAYLOAD),
+        uid: None,
+    };
+    for packet in packets(&record, Duration::ZERO, false) {
+        assert!(validate(&packet).is_ok());
+    }
+
+    let tag = "t".repeat(ANDROID_LOG_ENTRY_MAX_PAYLOAD);
+    let record = Record { tag: &tag, ..record };
+    let packets = packets(&record, Duration::ZERO, false);
+    assert!(matches!(validate(&packets[0]), Err(Error::RecordSize)));
+}
+
+#[test]
+fn packets_large_pid() {
+    let record = Record {
+        timestamp: std::time::SystemTime::now(),
+        pid: 4_194_303,
+        thread_id: 3,
+        buffer_id: Buffer::Main,
+        tag: "tag",
+        priority: Priority::Info,
+        message: "a",
+        uid: None,
+    };
+    let packets = packets(&record, Duration::new(1, 2), false);
+    let mut expected = BytesMut::new();
+    crate::byte_order().put_u16(&mut expected, (4_194_303u32 & 0xffff) as u16);
+    assert_eq!(&packets[0][5..7], &expected[..]);
+}
+
+#[test]
+fn interrupted_retried() {
+    use std::io::{Error, ErrorKind};
+
+    let mut calls = 0;
+    let result = retry_interrupted(|| {
+        calls += 1;
+        match calls {
+            1 | 2 => Err(Error::from(ErrorKind::Interrupted)),
+            _ => Ok(calls),
+        }
+    });
+    assert_eq!(result.unwrap(), 3);
+
+    let mut calls = 0;
+    let result = retry_interrupted(|| -> std::io::Result<()> {
+        calls += 1;
+        Err(Error::from(ErrorKind::Interrupted))
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, MAX_INTERRUPTED_RETRIES + 1);
+}
+
+#[test]
+fn partial_write_not_retried() {
+    use std::io::{self, Write};
+
+    /// Writer accepting `accept` bytes before failing.
+    struct Limited {
+        written: Vec<u8>,
+        accept: usize,
+    }
+
+    impl Write for Limited {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.accept - self.written.len());
+            if len == 0 {
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+            }
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let reopen = || {
+        Ok(Limited {
+            written: Vec::new(),
+            accept: 16,
+        })
+    };
+
+    let mut pmsg = Limited {
+        written: Vec::new(),
+        accept: 0,
+    };
+    write_or_reopen(&mut pmsg, b"packet", reopen).unwrap();
+    assert_eq!(pmsg.written, b"packet");
+
+    let mut pmsg = Limited {
+        written: Vec::new(),
+        accept: 3,
+    };
+    assert!(write_or_reopen(&mut pmsg, b"packet", reopen).is_err());
+    assert_eq!(pmsg.written, b"pac");
+}
@@ -0,0 +1,231 @@
+use log::{LevelFilter, Log, Metadata, SetLoggerError};
+use parking_lot::RwLock;
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Maximum number of records buffered before the logger is initialized. The
+/// oldest records are dropped if more records are logged.
+const CAPACITY: usize = 512;
+
+/// Global logger installed by [`capture_preinit`].
+static PREINIT: PreinitLogger = PreinitLogger {
+    state: parking_lot::const_rwlock(State::Buffering(VecDeque::new())),
+};
+
+/// Set if [`capture_preinit`] installed the logger and the logger has not yet
+/// been handed over by [`claim`].
+static CLAIMABLE: AtomicBool = AtomicBool::new(false);
+
+/// Record captured before the initialization of the logger.
+struct Captured {
+    level: log::Level,
+    target: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    message: String,
+}
+
+enum State {
+    /// Records are buffered until the logger is initialized.
+    Buffering(VecDeque<Captured>),
+    /// Records are forwarded to the initialized logger.
+    Forwarding(Box<dyn Log>),
+}
+
+/// Logger that buffers records until the real logger is initialized.
+struct PreinitLogger {
+    state: RwLock<State>,
+}
+
+impl PreinitLogger {
+    /// Replays the captured records into `logger` and forwards all further records.
+    ///
+    /// The records are replayed without holding the lock, so `logger` may log
+    /// while handling a replayed record.
+    fn forward(&self, logger: Box<dyn Log>) {
+        let records = match mem::replace(&mut *self.state.write(), State::Forwarding(logger)) {
+            State::Buffering(records) => records,
+            State::Forwarding(_) => VecDeque::new(),
+        };
+        for captured in &records {
+            replay(self, captured);
+        }
+    }
+}
+
+impl Log for PreinitLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match &*self.state.read() {
+            State::Buffering(_) => true,
+            State::Forwarding(logger) => logger.enabled(metadata),
+        }
+    }
+
+    fn log(&self, record: &log::Record) {
+        if let State::Forwarding(logger) = &*self.state.read() {
+            logger.log(record);
+            return;
+        }
+
+        let mut state = self.state.write();
+        match &mut *state {
+            State::Buffering(records) => {
+                if records.len() == CAPACITY {
+                    records.pop_front();
+                }
+                records.push_back(Captured {
+                    level: record.level(),
+                    target: record.target().to_string(),
+                    module_path: record.module_path().map(str::to_string),
+                    file: record.file().map(str::to_string),
+                    line: record.line(),
+                    message: record.args().to_string(),
+                });
+            }
+            State::Forwarding(logger) => logger.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        if let State::Forwarding(logger) = &*self.state.read() {
+            logger.flush();
+        }
+    }
+}
+
+/// Installs a buffering logger that captures records logged before the
+/// initialization of the logd logger.
+///
+/// Call this as early as possible. A subsequent [`init`](crate::Builder::init)
+/// replays the captured records with their original levels and targets into
+/// the logd logger. At most 512 records are buffered, older records are
+/// dropped.
+///
+/// This function fails if another global logger is already installed.
+///
+/// # Examples
+///
+/// ```
+/// android_logd_logger::capture_preinit().expect("failed to install the preinit logger");
+///
+/// log::info!("logged before init");
+///
+/// android_logd_logger::builder().init();
+/// ```
+pub fn capture_preinit() -> Result<(), SetLoggerError> {
+    log::set_logger(&PREINIT)?;
+    log::set_max_level(LevelFilter::Trace);
+    CLAIMABLE.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Returns true exactly once if the preinit logger is installed. The caller
+/// must hand over the logger with [`forward`].
+pub(crate) fn claim() -> bool {
+    CLAIMABLE.swap(false, Ordering::AcqRel)
+}
+
+/// Replays the captured records into `logger` and forwards all further records.
+pub(crate) fn forward(logger: Box<dyn Log>) {
+    PREINIT.forward(logger);
+}
+
+/// Logs a captured record to `logger`.
+fn replay(logger: &dyn Log, captured: &Captured) {
+    logger.log(
+        &log::Record::builder()
+            .level(captured.level)
+            .target(&captured.target)
+            .module_path(captured.module_path.as_deref())
+            .file(captured.file.as_deref())
+            .line(captured.line)
+            .args(format_args!("{}", captured.message))
+            .build(),
+    );
+}
+
+#[test]
+fn replay_in_order() {
+    use std::sync::{Arc, Mutex};
+
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl Log for Collect {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let entry = format!("{} {} {}", record.level(), record.target(), record.args());
+            self.0.lock().unwrap().push(entry);
+        }
+
+        fn flush(&self) {}
+    }
+
+    let preinit = PreinitLogger {
+        state: RwLock::new(State::Buffering(VecDeque::new())),
+    };
+    for n in 0..CAPACITY + 1 {
+        let args = format!("{}", n);
+        preinit.log(
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("early")
+                .args(format_args!("{}", args))
+                .build(),
+        );
+    }
+
+    let entries = Arc::new(Mutex::new(Vec::new()));
+    preinit.forward(Box::new(Collect(entries.clone())));
+    preinit.log(
+        &log::Record::builder()
+            .level(log::Level::Info)
+            .target("late")
+            .args(format_args!("x"))
+            .build(),
+    );
+
+    let entries = entries.lock().unwrap();
+    assert_eq!(entries.len(), CAPACITY + 1);
+    assert_eq!(entries[0], "WARN early 1");
+    assert_eq!(entries[CAPACITY - 1], format!("WARN early {}", CAPACITY));
+    assert_eq!(entries[CAPACITY], "INFO late x");
+}
+
+#[test]
+fn replay_reentrant() {
+    use std::sync::{Arc, Mutex};
+
+    static NESTED: PreinitLogger = PreinitLogger {
+        state: parking_lot::const_rwlock(State::Buffering(VecDeque::new())),
+    };
+
+    /// Logs every replayed record again, e.g. like an error handler.
+    struct Echo(Arc<Mutex<Vec<String>>>);
+
+    impl Log for Echo {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(format!("{} {}", record.target(), record.args()));
+            if record.target() == "early" {
+                NESTED.log(&log::Record::builder().target("echo").args(*record.args()).build());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    NESTED.log(&log::Record::builder().target("early").args(format_args!("x")).build());
+    let entries = Arc::new(Mutex::new(Vec::new()));
+    NESTED.forward(Box::new(Echo(entries.clone())));
+    assert_eq!(*entries.lock().unwrap(), ["early x", "echo x"]);
+}
@@ -0,0 +1,163 @@
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Maximum number of tracked tags. Expired windows are evicted first, then
+/// the window started first.
+const MAX_TAGS: usize = 256;
+
+/// Records of a tag admitted and dropped in the current window.
+struct Window {
+    start: Instant,
+    admitted: u32,
+    dropped: u32,
+}
+
+/// Per tag rate limit. See [`Builder::rate_limit`](crate::Builder::rate_limit).
+///
+/// Each tag may log `max` records per `interval`. The window of a tag starts
+/// with its first record and is reset with the first record after the
+/// interval elapsed.
+pub(crate) struct RateLimiter {
+    max: u32,
+    interval: Duration,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl RateLimiter {
+    /// Create a limiter admitting `max` records per tag and `interval`.
+    pub(crate) fn new(max: u32, interval: Duration) -> RateLimiter {
+        RateLimiter {
+            max,
+            interval,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check a record of `tag` at `now`.
+    ///
+    /// Returns `None` if the record is dropped. Otherwise the number of
+    /// records of `tag` dropped in the previous window is returned once.
+    pub(crate) fn admit(&self, tag: &str, now: Instant) -> Option<u32> {
+        let mut windows = self.windows.lock();
+        if windows.len() >= MAX_TAGS && !windows.contains_key(tag) {
+            let interval = self.interval;
+            windows.retain(|_, window| now.saturating_duration_since(window.start) < interval);
+            if windows.len() >= MAX_TAGS {
+                let oldest = windows
+                    .iter()
+                    .min_by_key(|(_, window)| window.start)
+                    .map(|(tag, _)| tag.clone());
+                if let Some(oldest) = oldest {
+                    windows.remove(&oldest);
+                }
+            }
+        }
+
+        let window = windows.entry(tag.to_string()).or_insert(Window {
+            start: now,
+            admitted: 0,
+            dropped: 0,
+        });
+
+        let mut suppressed = 0;
+        if now.saturating_duration_since(window.start) >= self.interval {
+            suppressed = window.dropped;
+            *window = Window {
+                start: now,
+                admitted: 0,
+                dropped: 0,
+            };
+        }
+
+        if window.admitted < self.max {
+            window.admitted += 1;
+            Some(suppressed)
+        } else {
+            window.dropped = window.dropped.saturating_add(1);
+            None
+        }
+    }
+
+    /// Take the number of records dropped per tag that are not reported yet.
+    /// The windows are kept and further records are still limited.
+    pub(crate) fn take_suppressed(&self) -> Vec<(String, u32)> {
+        let mut windows = self.windows.lock();
+        windows
+            .iter_mut()
+            .filter(|(_, window)| window.dropped > 0)
+            .map(|(tag, window)| (tag.clone(), std::mem::take(&mut window.dropped)))
+            .collect()
+    }
+}
+
+/// Message of the record reporting `count` dropped records.
+pub(crate) fn suppressed_message(count: u32) -> String {
+    format!("suppressed {} messages", count)
+}
+
+#[test]
+fn limited() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(1));
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    assert_eq!(limiter.admit("a", at(0)), Some(0));
+    assert_eq!(limiter.admit("a", at(100)), Some(0));
+    assert_eq!(limiter.admit("a", at(200)), None);
+    assert_eq!(limiter.admit("a", at(999)), None);
+    assert_eq!(limiter.admit("b", at(999)), Some(0));
+
+    // The window rolls over and the dropped records are reported once.
+    assert_eq!(limiter.admit("a", at(1000)), Some(2));
+    assert_eq!(limiter.admit("a", at(1001)), Some(0));
+    assert_eq!(limiter.admit("a", at(1002)), None);
+    assert_eq!(limiter.admit("b", at(1500)), Some(0));
+    assert_eq!(limiter.admit("a", at(5000)), Some(1));
+}
+
+#[test]
+fn evicted() {
+    let limiter = RateLimiter::new(1, Duration::from_secs(1));
+    let start = Instant::now();
+    for n in 0..MAX_TAGS {
+        limiter.admit(&n.to_string(), start);
+    }
+    assert_eq!(limiter.windows.lock().len(), MAX_TAGS);
+
+    limiter.admit("new", start + Duration::from_secs(1));
+    assert_eq!(limiter.windows.lock().len(), 1);
+
+    // Without expired windows the window started first is evicted.
+    for n in 0..MAX_TAGS {
+        limiter.admit(
+            &n.to_string(),
+            start + Duration::from_secs(2) + Duration::from_millis(n as u64),
+        );
+    }
+    assert_eq!(limiter.windows.lock().len(), MAX_TAGS);
+    assert!(!limiter.windows.lock().contains_key("new"));
+    limiter.admit(
+        "newer",
+        start + Duration::from_secs(2) + Duration::from_millis(MAX_TAGS as u64),
+    );
+    assert_eq!(limiter.windows.lock().len(), MAX_TAGS);
+    assert!(!limiter.windows.lock().contains_key("0"));
+}
+
+#[test]
+fn suppressed_taken() {
+    let limiter = RateLimiter::new(1, Duration::from_secs(1));
+    let start = Instant::now();
+    limiter.admit("a", start);
+    limiter.admit("a", start);
+    limiter.admit("a", start);
+    limiter.admit("b", start);
+    assert_eq!(limiter.take_suppressed(), [("a".to_string(), 2)]);
+    assert!(limiter.take_suppressed().is_empty());
+
+    // Reported drops are not reported again with the next window.
+    assert_eq!(limiter.admit("a", start + Duration::from_secs(1)), Some(0));
+}
@@ -0,0 +1,163 @@
+use crate::{Buffer, Priority, Record};
+use parking_lot::Mutex;
+
+/// Last record written and the number of its suppressed repetitions.
+struct Last {
+    buffer_id: Buffer,
+    tag: String,
+    priority: Priority,
+    message: String,
+    repeated: u32,
+}
+
+/// Repetitions of a record. See [`Builder::collapse_repeats`](crate::Builder::collapse_repeats).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Repeated {
+    pub(crate) buffer_id: Buffer,
+    pub(crate) tag: String,
+    pub(crate) priority: Priority,
+    pub(crate) count: u32,
+}
+
+impl Repeated {
+    /// Message of the record reporting the repetitions.
+    pub(crate) fn message(&self) -> String {
+        format!("last message repeated {} times", self.count)
+    }
+}
+
+/// Outcome of [`Repeats::push`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Push {
+    /// The record repeats the last record and is suppressed.
+    Repeated,
+    /// The record differs from the last record. Contains the repetitions of
+    /// the last record that are not reported yet.
+    New(Option<Repeated>),
+}
+
+/// Collapses consecutive identical records.
+///
+/// Records are identical if buffer, tag, priority and message match. The
+/// timestamp is ignored.
+#[derive(Default)]
+pub(crate) struct Repeats {
+    last: Mutex<Option<Last>>,
+}
+
+impl Repeats {
+    /// Compare `record` with the last record.
+    pub(crate) fn push(&self, record: &Record) -> Push {
+        let mut last = self.last.lock();
+        if let Some(last) = last.as_mut() {
+            if last.buffer_id == record.buffer_id
+                && last.priority == record.priority
+                && last.tag == record.tag
+                && last.message == record.message
+            {
+                last.repeated = last.repeated.saturating_add(1);
+                return Push::Repeated;
+            }
+        }
+
+        let repeated = last.take().and_then(Last::repeated);
+        *last = Some(Last {
+            buffer_id: record.buffer_id,
+            tag: record.tag.to_string(),
+            priority: record.priority,
+            message: record.message.to_string(),
+            repeated: 0,
+        });
+        Push::New(repeated)
+    }
+
+    /// Take the unreported repetitions of the last record. Further
+    /// repetitions are still suppressed.
+    pub(crate) fn take(&self) -> Option<Repeated> {
+        let mut last = self.last.lock();
+        let last = last.as_mut()?;
+        let repeated = Repeated {
+            buffer_id: last.buffer_id,
+            tag: last.tag.clone(),
+            priority: last.priority,
+            count: last.repeated,
+        };
+        last.repeated = 0;
+        Some(repeated).filter(|repeated| repeated.count > 0)
+    }
+}
+
+impl Last {
+    fn repeated(self) -> Option<Repeated> {
+        Some(Repeated {
+            buffer_id: self.buffer_id,
+            tag: self.tag,
+            priority: self.priority,
+            count: self.repeated,
+        })
+        .filter(|repeated| repeated.count > 0)
+    }
+}
+
+#[cfg(test)]
+fn record() -> Record<'static, 'static> {
+    Record {
+        timestamp: std::time::SystemTime::now(),
+        pid: 1,
+        thread_id: 2,
+        buffer_id: Buffer::Main,
+        tag: "tag",
+        priority: Priority::Info,
+        message: "again",
+        uid: None,
+    }
+}
+
+#[test]
+fn collapsed() {
+    let record = record();
+    let repeats = Repeats::default();
+    assert_eq!(repeats.push(&record), Push::New(None));
+    let later = Record {
+        timestamp: record.timestamp + std::time::Duration::from_secs(1),
+        ..record
+    };
+    assert_eq!(repeats.push(&later), Push::Repeated);
+    assert_eq!(repeats.push(&record), Push::Repeated);
+
+    let different = Record {
+        message: "different",
+        ..record
+    };
+    let repeated = Repeated {
+        buffer_id: Buffer::Main,
+        tag: "tag".into(),
+        priority: Priority::Info,
+        count: 2,
+    };
+    assert_eq!(repeats.push(&different), Push::New(Some(repeated)));
+    assert_eq!(repeats.take(), None);
+    assert_eq!(
+        repeats.push(&Record {
+            priority: Priority::Warn,
+            ..different
+        }),
+        Push::New(None)
+    );
+}
+
+#[test]
+fn flushed() {
+    let record = record();
+    let repeats = Repeats::default();
+    repeats.push(&record);
+    repeats.push(&record);
+    let repeated = repeats.take().unwrap();
+    assert_eq!(repeated.count, 1);
+    assert_eq!(repeated.message(), "last message repeated 1 times");
+    assert_eq!(repeats.take(), None);
+
+    // Still suppressed after the flush.
+    assert_eq!(repeats.push(&record), Push::Repeated);
+    assert_eq!(repeats.push(&Record { tag: "other", ..record }), Push::New(Some(repeated)));
+}
@@ -0,0 +1,79 @@
+use crate::{Priority, Record};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, time::SystemTime};
+
+/// Record retained in memory. See [`Builder::ring_buffer`](crate::Builder::ring_buffer).
+#[derive(Debug, Clone)]
+pub struct CapturedRecord {
+    /// Timestamp
+    pub timestamp: SystemTime,
+    /// Priority
+    pub priority: Priority,
+    /// Tag
+    pub tag: String,
+    /// Message
+    pub message: String,
+}
+
+/// Bounded in memory buffer of the most recent records.
+pub(crate) struct RingBuffer {
+    capacity: usize,
+    records: Mutex<VecDeque<CapturedRecord>>,
+}
+
+impl RingBuffer {
+    /// Create a new ring buffer retaining up to `capacity` records.
+    pub(crate) fn new(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Append `record` and drop the oldest record if the buffer is full.
+    pub(crate) fn push(&self, record: &Record) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let record = CapturedRecord {
+            timestamp: record.timestamp,
+            priority: record.priority,
+            tag: record.tag.to_string(),
+            message: record.message.to_string(),
+        };
+
+        let mut records = self.records.lock();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The retained records, oldest first.
+    pub(crate) fn records(&self) -> Vec<CapturedRecord> {
+        self.records.lock().iter().cloned().collect()
+    }
+}
+
+#[test]
+fn bounded() {
+    use crate::Buffer;
+
+    let ring_buffer = RingBuffer::new(2);
+    for message in ["a", "b", "c"] {
+        ring_buffer.push(&Record {
+            timestamp: SystemTime::now(),
+            pid: 0,
+            thread_id: 0,
+            buffer_id: Buffer::Main,
+            tag: "tag",
+            priority: Priority::Info,
+            message,
+            uid: None,
+        });
+    }
+
+    let messages = ring_buffer.records().into_iter().map(|r| r.message).collect::<Vec<_>>();
+    assert_eq!(messages, ["b", "c"]);
+}
@@ -0,0 +1,107 @@
+use crate::{write_event_buffer_now, Buffer, EventTag};
+use std::{
+    io,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Snapshot of the logger health counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Counters {
+    /// Number of datagrams handed to logd.
+    pub(crate) sent: u64,
+    /// Number of datagrams discarded.
+    pub(crate) dropped: u64,
+    /// Number of reconnect attempts.
+    pub(crate) reconnects: u64,
+}
+
+impl Counters {
+    /// Difference of the counters since `earlier`.
+    fn since(&self, earlier: &Counters) -> Counters {
+        Counters {
+            sent: self.sent.wrapping_sub(earlier.sent),
+            dropped: self.dropped.wrapping_sub(earlier.dropped),
+            reconnects: self.reconnects.wrapping_sub(earlier.reconnects),
+        }
+    }
+}
+
+/// Current counter values. Only the logd socket on Android maintains
+/// counters. Records logged from the error handler are counted as dropped.
+pub(crate) fn counters() -> Counters {
+    #[cfg(target_os = "android")]
+    let counters = crate::logd::counters();
+    #[cfg(not(target_os = "android"))]
+    let counters = Counters::default();
+
+    Counters {
+        dropped: counters.dropped + crate::logger::dropped_in_error_handler(),
+        ..counters
+    }
+}
+
+/// Background thread that periodically writes the counter deltas as an event
+/// to [`Buffer::Stats`].
+pub(crate) struct StatsEmitter {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl StatsEmitter {
+    /// Spawn the emitter thread. An event with the tag `tag` and the value
+    /// `(sent, dropped, reconnects)` is written every `interval`.
+    pub(crate) fn spawn(tag: EventTag, interval: Duration) -> io::Result<StatsEmitter> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new().name("logd-stats".into()).spawn(move || {
+            let mut last = counters();
+            // Any message or a disconnected sender terminates the loop.
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let current = counters();
+                let delta = current.since(&last);
+                last = current;
+
+                let value = (delta.sent as i64, delta.dropped as i64, delta.reconnects as i64);
+                write_event_buffer_now(Buffer::Stats, tag, value).ok();
+            }
+        })?;
+
+        Ok(StatsEmitter { stop, thread })
+    }
+
+    /// Stop the emitter thread and wait for it to terminate.
+    pub(crate) fn stop(self) {
+        self.stop.send(()).ok();
+        self.thread.join().ok();
+    }
+}
+
+#[test]
+fn counters_since() {
+    let earlier = Counters {
+        sent: 10,
+        dropped: 1,
+        reconnects: 0,
+    };
+    let current = Counters {
+        sent: 15,
+        dropped: 3,
+        reconnects: 1,
+    };
+    assert_eq!(
+        current.since(&earlier),
+        Counters {
+            sent: 5,
+            dropped: 2,
+            reconnects: 1
+        }
+    );
+}
+
+#[test]
+fn emitter_stops() {
+    let emitter = StatsEmitter::spawn(1, Duration::from_millis(1)).expect("failed to spawn");
+    thread::sleep(Duration::from_millis(10));
+    emitter.stop();
+}
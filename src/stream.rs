@@ -0,0 +1,157 @@
+use crate::{byte_order, diagnostics, logd, Error, Record};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    io,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// Collector records are written to. See [`Builder::stream_collector`](crate::Builder::stream_collector).
+static COLLECTOR: RwLock<Option<Arc<StreamCollector>>> = parking_lot::const_rwlock(None);
+
+/// Write records only to the collector. See [`Builder::stream_collector_only`](crate::Builder::stream_collector_only).
+pub(crate) static EXCLUSIVE: AtomicBool = AtomicBool::new(false);
+
+/// Connection to a log collector listening on a Unix stream socket.
+///
+/// Each record is written as its logd datagram prefixed with the length of
+/// the datagram as `u32` in the configured byte order. Writes block if the
+/// collector does not keep up.
+struct StreamCollector {
+    path: PathBuf,
+    /// Current connection and the time of the last connect attempt.
+    stream: Mutex<(Option<UnixStream>, Option<Instant>)>,
+}
+
+impl StreamCollector {
+    fn new(path: &Path) -> StreamCollector {
+        StreamCollector {
+            path: path.to_owned(),
+            stream: Mutex::new((None, None)),
+        }
+    }
+
+    /// Write `frame` to the collector. If the write fails, reconnect and
+    /// try again once. Connect attempts are limited to one within the
+    /// configured reconnect interval.
+    fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let mut guard = self.stream.lock();
+        let (stream, last_attempt) = &mut *guard;
+
+        if let Some(connected) = stream {
+            if write_all(connected, frame).is_ok() {
+                return Ok(());
+            }
+            *stream = None;
+        }
+
+        let interval = crate::reconnect_interval();
+        if matches!(last_attempt, Some(last) if last.elapsed() < interval) {
+            return Err(io::Error::from(io::ErrorKind::NotConnected));
+        }
+        *last_attempt = Some(Instant::now());
+
+        let mut connected = UnixStream::connect(&self.path)?;
+        write_all(&mut connected, frame)?;
+        *stream = Some(connected);
+        Ok(())
+    }
+}
+
+/// Write `buffer` to `stream` without raising `SIGPIPE` if the collector
+/// closed the connection.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn write_all(stream: &mut UnixStream, mut buffer: &[u8]) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    while !buffer.is_empty() {
+        // Safety: the pointer and length reference the valid slice `buffer`.
+        let sent = unsafe {
+            libc::send(
+                stream.as_raw_fd(),
+                buffer.as_ptr() as *const libc::c_void,
+                buffer.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        match sent {
+            n if n < 0 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            0 => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            n => buffer = &buffer[n as usize..],
+        }
+    }
+    Ok(())
+}
+
+/// Write `buffer` to `stream`.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn write_all(stream: &mut UnixStream, buffer: &[u8]) -> io::Result<()> {
+    io::Write::write_all(stream, buffer)
+}
+
+/// Use the collector listening at `path`.
+pub(crate) fn set_collector(path: &Path) {
+    *COLLECTOR.write() = Some(Arc::new(StreamCollector::new(path)));
+}
+
+/// Write `record` to the collector if one is configured. Returns true if
+/// the record was written and records are written only to the collector.
+/// On failure the record is left to the other outputs.
+pub(crate) fn log(record: &Record) -> Result<bool, Error> {
+    let collector = match COLLECTOR.read().clone() {
+        Some(collector) => collector,
+        None => return Ok(false),
+    };
+
+    let result = logd::datagrams(record).try_for_each(|datagram| {
+        let mut frame = Vec::with_capacity(4 + datagram.len());
+        byte_order().put_u32(&mut frame, datagram.len() as u32);
+        frame.extend_from_slice(&datagram);
+        collector.send(&frame)
+    });
+    match result {
+        Ok(()) => Ok(EXCLUSIVE.load(Ordering::Relaxed)),
+        Err(e) => {
+            diagnostics::report("stream", format_args!("failed to write to the collector: {}", e));
+            Err(e.into())
+        }
+    }
+}
+
+#[test]
+fn reconnect() {
+    use std::{io::Read, os::unix::net::UnixListener};
+
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("collector");
+    let collector = StreamCollector::new(&path);
+    assert!(collector.send(b"lost").is_err());
+
+    let listener = UnixListener::bind(&path).unwrap();
+    collector.send(b"first").unwrap();
+    let (mut first, _) = listener.accept().unwrap();
+    let mut buffer = [0u8; 5];
+    first.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"first");
+
+    // The collector closes the connection. The first write after the close
+    // may still succeed, the next fails and reconnects.
+    drop(first);
+    collector.send(b"x").ok();
+    collector.send(b"again").unwrap();
+    let (mut second, _) = listener.accept().unwrap();
+    let mut received = Vec::new();
+    second.set_nonblocking(true).unwrap();
+    second.read_to_end(&mut received).ok();
+    assert!(received.ends_with(b"again"));
+}
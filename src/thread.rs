@@ -1,6 +1,48 @@
+/// Cache the thread id per thread. See [`Builder::cache_thread_id`](crate::Builder::cache_thread_id).
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(unused))]
+pub(crate) static CACHE_ID: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+thread_local! {
+    /// Id of the current thread. Zero until the first call of `id`.
+    static ID: std::cell::Cell<i32> = const { std::cell::Cell::new(0) };
+}
+
 #[cfg(any(target_os = "linux", target_os = "android"))]
 #[inline]
 pub fn id() -> i32 {
+    if !CACHE_ID.load(std::sync::atomic::Ordering::Relaxed) {
+        return gettid();
+    }
+
+    ID.with(|id| match id.get() {
+        0 => {
+            let tid = gettid();
+            id.set(tid);
+            REGISTER_ATFORK.call_once(|| {
+                // Safety: the handler only resets a thread local.
+                unsafe { libc::pthread_atfork(None, None, Some(reset_after_fork)) };
+            });
+            tid
+        }
+        tid => tid,
+    })
+}
+
+/// Registers [`reset_after_fork`] once.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+static REGISTER_ATFORK: std::sync::Once = std::sync::Once::new();
+
+/// Clears the cached id in the child of a `fork`. The forking thread is the
+/// only thread of the child and has a new id.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+extern "C" fn reset_after_fork() {
+    ID.try_with(|id| id.set(0)).ok();
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline]
+fn gettid() -> i32 {
     #[allow(clippy::unnecessary_cast)]
     unsafe {
         libc::gettid() as i32
@@ -28,3 +70,30 @@ pub fn id() -> i32 {
     // Each thread has a separate pid on Redox.
     syscall::getpid().unwrap() as i32
 }
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn cached() {
+    assert_eq!(id(), gettid());
+    assert_eq!(id(), gettid());
+    let other = std::thread::spawn(id).join().unwrap();
+    assert_ne!(other, id());
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn reset_in_child() {
+    id();
+    // Safety: the child only queries its thread id and exits.
+    unsafe {
+        match libc::fork() {
+            0 => libc::_exit(if id() == gettid() { 0 } else { 1 }),
+            pid => {
+                let mut status = 0;
+                assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+                assert!(libc::WIFEXITED(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+            }
+        }
+    }
+}
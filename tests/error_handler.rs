@@ -0,0 +1,51 @@
+#![cfg(not(target_os = "android"))]
+
+use android_logd_logger::{Buffer, Logger, Priority};
+use log::LevelFilter;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::SystemTime,
+};
+
+/// Writer failing every write.
+struct Broken;
+
+impl io::Write for Broken {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("broken"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn reconfigured_from_handler() {
+    let logger = android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .target_writer(Box::new(Broken))
+        .on_error(|_| {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+            // Writes from the handler are dropped instead of recursing.
+            log::error!("failed");
+            android_logd_logger::log(SystemTime::now(), Buffer::Main, Priority::Error, 0, 0, "tag", "failed").unwrap();
+            // The configuration is not locked while the handler runs.
+            if let Some(logger) = LOGGER.get() {
+                logger.filter_level(LevelFilter::Debug);
+            }
+        })
+        .init();
+    LOGGER.set(logger.clone()).ok();
+
+    log::info!("lost");
+    assert_eq!(ERRORS.load(Ordering::Relaxed), 1);
+    assert_eq!(logger.level(), LevelFilter::Debug);
+}
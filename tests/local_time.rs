@@ -0,0 +1,25 @@
+//! Runs without the test harness: the time zone is only read in a single
+//! threaded process.
+
+mod common;
+
+use common::Sink;
+use log::LevelFilter;
+
+fn main() {
+    // Pin the local time zone to UTC+02:30.
+    std::env::set_var("TZ", "XYZ-02:30");
+
+    let sink = Sink::default();
+    android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .timestamp_format("[offset_hour sign:mandatory]:[offset_minute]")
+        .unwrap()
+        .local_time(true)
+        .target_writer(Box::new(sink.clone()))
+        .init();
+
+    log::info!("local");
+    let output = sink.take();
+    assert!(output.starts_with("+02:30 "), "{}", output);
+}
@@ -0,0 +1,50 @@
+#![cfg(not(target_os = "windows"))]
+
+use android_logd_logger::Buffer;
+use log::LevelFilter;
+use std::{
+    io::{self, Read},
+    os::unix::net::UnixListener,
+    time::Duration,
+};
+
+#[test]
+fn mirrored() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let path = tempdir.path().join("collector");
+    let listener = UnixListener::bind(&path).unwrap();
+    android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .buffer(Buffer::Custom(220))
+        .mirror_buffers(&[
+            Buffer::Custom(221),
+            Buffer::Custom(220),
+            Buffer::Custom(222),
+            Buffer::Custom(221),
+        ])
+        .stream_collector_only(&path)
+        .init();
+
+    log::info!("mirrored");
+
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut datagrams = Vec::new();
+    for _ in 0..3 {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut datagram = vec![0u8; u32::from_le_bytes(len) as usize];
+        stream.read_exact(&mut datagram).unwrap();
+        datagrams.push(datagram);
+    }
+    stream.set_nonblocking(true).unwrap();
+    let error = stream.read(&mut [0u8; 1]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+
+    // Each buffer is written once and only the buffer id differs.
+    assert!(datagrams.iter().all(|datagram| datagram[1..] == datagrams[0][1..]));
+    assert_eq!(
+        datagrams.iter().map(|datagram| datagram[0]).collect::<Vec<_>>(),
+        [220, 221, 222]
+    );
+}
@@ -0,0 +1,27 @@
+#![cfg(not(target_os = "android"))]
+
+mod common;
+
+use common::Sink;
+
+#[test]
+fn panic_logged() {
+    let sink = Sink::default();
+    android_logd_logger::builder().target_writer(Box::new(sink.clone())).init();
+
+    android_logd_logger::install_panic_hook();
+    let result = std::thread::Builder::new()
+        .name("worker".into())
+        .spawn(|| panic!("boom"))
+        .unwrap()
+        .join();
+    assert!(result.is_err());
+
+    let output = sink.take();
+    assert!(output.contains(" E panic: thread 'worker' panicked at tests/panic_hook.rs:"));
+    assert!(output.contains("\nboom"));
+
+    android_logd_logger::uninstall_panic_hook();
+    assert!(std::thread::spawn(|| panic!("not logged")).join().is_err());
+    assert!(sink.take().is_empty());
+}
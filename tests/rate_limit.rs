@@ -0,0 +1,32 @@
+#![cfg(not(target_os = "android"))]
+
+mod common;
+
+use common::Sink;
+use log::LevelFilter;
+use std::time::Duration;
+
+#[test]
+fn drops_reported_on_flush() {
+    let sink = Sink::default();
+    android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .tag("limited")
+        .rate_limit(1, Duration::from_secs(3600))
+        .target_writer(Box::new(sink.clone()))
+        .init();
+
+    log::info!("first");
+    log::info!("second");
+    log::info!("third");
+    let output = sink.take();
+    assert!(output.contains("first"));
+    assert!(!output.contains("second"));
+
+    log::logger().flush();
+    let output = sink.take();
+    assert!(output.contains(" W limited: suppressed 2 messages"));
+
+    log::logger().flush();
+    assert!(sink.take().is_empty());
+}
@@ -0,0 +1,29 @@
+#![cfg(not(target_os = "android"))]
+
+mod common;
+
+use common::Sink;
+use log::LevelFilter;
+use std::{thread, time::Duration};
+
+#[test]
+fn rate_limit_summary_not_collapsed() {
+    let sink = Sink::default();
+    android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .tag("repeated")
+        .rate_limit(1, Duration::from_millis(50))
+        .collapse_repeats(true)
+        .target_writer(Box::new(sink.clone()))
+        .init();
+
+    log::info!("again");
+    log::info!("again");
+    thread::sleep(Duration::from_millis(100));
+    // Collapsed as a repetition, but the drop before it is still reported.
+    log::info!("again");
+
+    let output = sink.take();
+    assert_eq!(output.matches("again").count(), 1);
+    assert!(output.contains(" W repeated: suppressed 1 messages"));
+}
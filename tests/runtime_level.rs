@@ -0,0 +1,28 @@
+mod common;
+
+use common::Sink;
+use log::LevelFilter;
+
+#[test]
+fn raised_at_runtime() {
+    let sink = Sink::default();
+    let logger = android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .target_writer(Box::new(sink.clone()))
+        .init();
+    assert_eq!(log::max_level(), LevelFilter::Info);
+
+    log::trace!("suppressed");
+    logger.filter_level(LevelFilter::Trace);
+    assert_eq!(log::max_level(), LevelFilter::Trace);
+    log::trace!("passed");
+
+    let output = sink.take();
+    assert!(!output.contains("suppressed"));
+    assert!(output.contains("passed"));
+
+    logger.filter_level(LevelFilter::Warn);
+    assert_eq!(log::max_level(), LevelFilter::Warn);
+    logger.filter_module("foo", LevelFilter::Debug);
+    assert_eq!(log::max_level(), LevelFilter::Debug);
+}
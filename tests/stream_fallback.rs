@@ -0,0 +1,28 @@
+#![cfg(not(any(target_os = "android", target_os = "windows")))]
+
+mod common;
+
+use common::Sink;
+use log::LevelFilter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn collector_gone() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let sink = Sink::default();
+    android_logd_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .stream_collector_only(tempdir.path().join("collector"))
+        .target_writer(Box::new(sink.clone()))
+        .on_error(|_| {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+        })
+        .init();
+
+    log::info!("not collected");
+    assert!(sink.take().contains("not collected"));
+    // The failed send reaches the callback.
+    assert_eq!(ERRORS.load(Ordering::Relaxed), 1);
+}